@@ -1,3 +1,23 @@
 dark_mode = Dark Mode
 gamma_map = Gamma Map
-refresh = Refresh
\ No newline at end of file
+refresh = Refresh
+contrast = Contrast
+input_source = Input Source
+unsupported = { $name } does not support brightness control
+status_permission_denied = No permission to access { $name }, check the i2c permissions
+status_no_reply = { $name } does not reply, DDC/CI may be disabled in its menu
+status_checksum_error = { $name } sent corrupted data
+status_error = Can't communicate with { $name }
+retry = Retry
+builtin_display = Built-in Display
+not_confirmed = The monitor can't report its brightness, this value is the last one set
+link = Linked
+range_floor = Hardware brightness at 0%
+range_ceiling = Hardware brightness at 100%
+custom_curve = Custom curve
+schedule = Schedule
+ambient_light = Automatic brightness
+follow_internal = Follow the built-in display
+link_none = Not linked
+link_group = Group { $index }
+link_new = New group
\ No newline at end of file
@@ -0,0 +1,191 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use cosmic::iced::{
+    Subscription,
+    futures::{SinkExt, Stream, channel::mpsc::Sender},
+    stream,
+};
+
+use crate::app::AppMsg;
+
+/// Where the kernel exposes the light sensors
+const IIO_ROOT: &str = "/sys/bus/iio/devices";
+
+/// Env var used to read the light sensor from another directory than sysfs
+const IIO_ROOT_ENV: &str = "EXTERNAL_MONITOR_BRIGHTNESS_IIO_ROOT";
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Weight of a new sample in the moving average of the illuminance
+const SMOOTHING: f32 = 0.3;
+
+/// Illuminance channel of an iio device, e.g. `in_illuminance_raw`
+struct LightSensor {
+    value: PathBuf,
+    scale: f32,
+    offset: f32,
+}
+
+impl LightSensor {
+    /// First device of the root with an illuminance channel
+    fn find(root: &Path) -> Option<Self> {
+        let mut devices: Vec<_> = fs::read_dir(root)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect();
+        devices.sort();
+
+        devices.iter().find_map(|device| Self::from_device(device))
+    }
+
+    fn from_device(device: &Path) -> Option<Self> {
+        let mut channels: Vec<_> = fs::read_dir(device)
+            .ok()?
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| name.starts_with("in_illuminance"))
+            .collect();
+        // in lux already, no scale to apply
+        channels.sort_by_key(|name| !name.ends_with("_input"));
+
+        let (name, prefix) = channels.iter().find_map(|name| {
+            let prefix = name
+                .strip_suffix("_input")
+                .or_else(|| name.strip_suffix("_raw"))?;
+            Some((name, prefix))
+        })?;
+
+        let read_f32 = |suffix: &str| {
+            fs::read_to_string(device.join(format!("{prefix}_{suffix}")))
+                .ok()
+                .and_then(|v| v.trim().parse::<f32>().ok())
+        };
+
+        let (scale, offset) = if name.ends_with("_raw") {
+            (
+                read_f32("scale").unwrap_or(1.0),
+                read_f32("offset").unwrap_or(0.0),
+            )
+        } else {
+            (1.0, 0.0)
+        };
+
+        info!("ambient light sensor: {}", device.join(name).display());
+
+        Some(Self {
+            value: device.join(name),
+            scale,
+            offset,
+        })
+    }
+
+    /// Illuminance in lux
+    fn read(&self) -> io::Result<f32> {
+        let raw = fs::read_to_string(&self.value)?;
+        let raw: f32 = raw
+            .trim()
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+        Ok((raw + self.offset) * self.scale)
+    }
+}
+
+/// Smoothed illuminance of the ambient light sensor
+pub fn sub() -> Subscription<AppMsg> {
+    Subscription::run(illuminance)
+}
+
+fn illuminance() -> impl Stream<Item = AppMsg> {
+    stream::channel(10, |mut output: Sender<AppMsg>| async move {
+        let root = std::env::var(IIO_ROOT_ENV).unwrap_or_else(|_| IIO_ROOT.to_owned());
+
+        let Some(sensor) = LightSensor::find(Path::new(&root)) else {
+            warn!("no ambient light sensor in {root}");
+            return;
+        };
+
+        let mut smoothed: Option<f32> = None;
+
+        loop {
+            match sensor.read() {
+                Ok(lux) => {
+                    let lux = smoothed.map_or(lux, |s| s + SMOOTHING * (lux - s));
+                    smoothed = Some(lux);
+
+                    if output.send(AppMsg::AmbientLight(lux)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => warn!("can't read ambient light sensor: {e}"),
+            }
+
+            tokio::time::sleep(SAMPLE_INTERVAL).await;
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn raw_channel_with_scale_and_offset() {
+        let root = TempDir::new();
+        root.write("iio:device0/in_accel_x_raw", "12");
+        root.write("iio:device1/in_illuminance_raw", "200\n");
+        root.write("iio:device1/in_illuminance_scale", "0.5\n");
+        root.write("iio:device1/in_illuminance_offset", "10\n");
+
+        let sensor = LightSensor::find(root.path()).unwrap();
+        assert_eq!(sensor.read().unwrap(), 105.0);
+
+        root.write("iio:device1/in_illuminance_raw", "0\n");
+        assert_eq!(sensor.read().unwrap(), 5.0);
+    }
+
+    #[test]
+    fn raw_channel_without_scale() {
+        let root = TempDir::new();
+        root.write("iio:device0/in_illuminance_raw", "42");
+
+        let sensor = LightSensor::find(root.path()).unwrap();
+        assert_eq!(sensor.read().unwrap(), 42.0);
+    }
+
+    #[test]
+    fn prefers_input_channel() {
+        let root = TempDir::new();
+        root.write("iio:device0/in_illuminance_raw", "200");
+        root.write("iio:device0/in_illuminance_scale", "0.5");
+        root.write("iio:device0/in_illuminance_input", "321.5");
+
+        let sensor = LightSensor::find(root.path()).unwrap();
+        // already in lux, the scale of the raw channel doesn't apply
+        assert_eq!(sensor.read().unwrap(), 321.5);
+    }
+
+    #[test]
+    fn no_sensor() {
+        let root = TempDir::new();
+        root.write("iio:device0/in_accel_x_raw", "12");
+
+        assert!(LightSensor::find(root.path()).is_none());
+        assert!(LightSensor::find(&root.path().join("missing")).is_none());
+    }
+
+    #[test]
+    fn invalid_value() {
+        let root = TempDir::new();
+        root.write("iio:device0/in_illuminance_input", "not a number");
+
+        let sensor = LightSensor::find(root.path()).unwrap();
+        assert!(sensor.read().is_err());
+    }
+}
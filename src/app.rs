@@ -1,47 +1,173 @@
-use std::collections::HashMap;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use crate::config::{self, Config, MonitorConfig};
+use crate::ambient;
+use crate::backend::is_backlight;
+use crate::capabilities::Capabilities;
+use crate::config::{self, Config, Link, LinkGroup, MonitorConfig};
+use crate::curve::Curve;
+use crate::follow;
 use crate::monitor;
-use crate::monitor::{DisplayId, EventToSub, MonitorInfo, ScreenBrightness};
+use crate::monitor::{
+    DisplayId, EventToSub, INPUT_SOURCE_CODE, MonitorInfo, MonitorStatus, ScreenBrightness,
+};
+use crate::output::{self, WaylandOutput};
+use crate::schedule;
 use anyhow::anyhow;
+use chrono::Local;
 use cosmic::Element;
 use cosmic::app::{Core, Task};
+use cosmic::cctk::wayland_client::backend::ObjectId;
 use cosmic::cosmic_config::Config as CosmicConfig;
 use cosmic::cosmic_config::CosmicConfigEntry;
 use cosmic::cosmic_theme::{THEME_MODE_ID, ThemeMode};
 use cosmic::iced::core::window;
 use cosmic::iced::platform_specific::shell::commands::popup::{destroy_popup, get_popup};
 use cosmic::iced::window::Id;
-use cosmic::iced::{Limits, Subscription};
+use cosmic::iced::{Limits, Subscription, time};
 use cosmic::widget::Space;
-use tokio::sync::watch::Sender;
+use tokio::sync::mpsc::UnboundedSender;
 
 pub const APPID: &str = "io.github.cosmic_utils.cosmic-ext-applet-external-monitor-brightness";
 
+/// Smallest part of the hardware range between the floor and the ceiling of a monitor
+const MIN_RANGE: f32 = 0.05;
+
+/// Change of the sliders ignored when following the ambient light, to avoid flicker
+const AMBIENT_HYSTERESIS: f32 = 0.05;
+
+/// Time without change before the brightness of a write-only monitor is saved
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+/// Time after a write to the internal panel during which its changes are not followed,
+/// they come from the applet. The transition of the brightness is added to it.
+const BACKLIGHT_ECHO_DELAY: Duration = Duration::from_secs(1);
+
+/// Difference of level of the internal panel, or of the sliders,
+/// still considered the same position
+const LEVEL_TOLERANCE: f32 = 0.01;
+
+/// Slider values of the control points of a new custom curve
+const CURVE_POINTS: [f32; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
 #[derive(Debug, Clone)]
 pub struct MonitorState {
     pub name: String,
     /// Between 0 and 1
     pub slider_brightness: f32,
+    /// Maximum brightness value reported by the monitor
+    pub max_brightness: u16,
+    /// Between 0 and max_contrast, None if the monitor doesn't support it
+    pub contrast: Option<u16>,
+    pub max_contrast: u16,
+    pub input_source: Option<u16>,
+    /// None if the capabilities string couldn't be fetched
+    pub capabilities: Option<Capabilities>,
+    pub status: MonitorStatus,
     pub settings_expanded: bool,
+    /// Name of the DRM connector, e.g. `DP-2`
+    pub connector: Option<String>,
+    /// Wayland output showing this monitor, None if it wasn't matched
+    pub output: Option<WaylandOutput>,
+}
+
+/// Position of an external monitor when the internal panel was at a level.
+/// The changes of the panel are applied relatively to it, so they can be undone.
+#[derive(Debug, Clone, Copy)]
+struct FollowAnchor {
+    level: f32,
+    slider_brightness: f32,
+    /// Last position set by following, the anchor is dropped if the slider moved since
+    followed: f32,
 }
 
-pub fn get_mapped_brightness(slider_brightness: f32, gamma: f32) -> u16 {
-    (slider_brightness.powf(gamma) * 100.0).round() as u16
+/// Part of the hardware range for a slider value, with the gamma and the range of the monitor
+fn gamma_level(slider_brightness: f32, config: &MonitorConfig) -> f32 {
+    let span = config.ceiling - config.floor;
+    config.floor + slider_brightness.powf(config.gamma_map) * span
+}
+
+pub fn get_mapped_brightness(
+    slider_brightness: f32,
+    config: &MonitorConfig,
+    max_brightness: u16,
+) -> u16 {
+    let level = match &config.curve {
+        Some(curve) => curve.eval(slider_brightness),
+        None => gamma_level(slider_brightness, config),
+    };
+
+    (level.clamp(0.0, 1.0) * max_brightness as f32).round() as u16
+}
+
+pub fn get_slider_brightness(brightness: u16, config: &MonitorConfig, max_brightness: u16) -> f32 {
+    let level = brightness as f32 / max_brightness as f32;
+
+    if let Some(curve) = &config.curve {
+        return curve.inverse(level).clamp(0.0, 1.0);
+    }
+
+    let span = config.ceiling - config.floor;
+    if span <= 0.0 {
+        return 0.0;
+    }
+
+    ((level - config.floor) / span)
+        .clamp(0.0, 1.0)
+        .powf(1.0 / config.gamma_map)
 }
 
-pub fn get_slider_brightness(brightness: u16, gamma: f32) -> f32 {
-    (brightness as f32 / 100.0).powf(1.0 / gamma)
+/// Percentage of a value in the range reported by the monitor
+pub fn to_percent(value: u16, max: u16) -> u16 {
+    (value as f32 * 100.0 / max as f32).round() as u16
 }
 
 impl MonitorState {
-    pub fn get_mapped_brightness(&self, gamma: f32) -> u16 {
-        get_mapped_brightness(self.slider_brightness, gamma)
+    pub fn get_mapped_brightness(&self, config: &MonitorConfig) -> u16 {
+        get_mapped_brightness(self.slider_brightness, config, self.max_brightness)
+    }
+
+    pub fn set_slider_brightness(&mut self, brightness: u16, config: &MonitorConfig) {
+        self.slider_brightness = get_slider_brightness(brightness, config, self.max_brightness)
+    }
+
+    pub fn get_brightness_percent(&self, config: &MonitorConfig) -> u16 {
+        to_percent(self.get_mapped_brightness(config), self.max_brightness)
+    }
+
+    /// Whether the brightness of the monitor can be controlled
+    pub fn is_available(&self) -> bool {
+        matches!(
+            self.status,
+            MonitorStatus::Available | MonitorStatus::WriteOnly
+        )
+    }
+
+    /// Whether the brightness shown was reported by the monitor
+    pub fn is_confirmed(&self) -> bool {
+        self.status != MonitorStatus::WriteOnly
+    }
+
+    /// Name shown to the user, with the connector to tell identical monitors apart
+    pub fn label(&self) -> String {
+        let name = match &self.output {
+            Some(output) if self.name.is_empty() => format!("{} {}", output.make, output.model),
+            _ => self.name.clone(),
+        };
+
+        match &self.connector {
+            Some(connector) if name.trim().is_empty() => connector.clone(),
+            Some(connector) => format!("{name} ({connector})"),
+            None => name,
+        }
     }
 
-    pub fn set_slider_brightness(&mut self, brightness: u16, gamma: f32) {
-        self.slider_brightness = get_slider_brightness(brightness, gamma)
+    /// Input sources advertised in the capabilities string
+    pub fn input_sources(&self) -> &[u16] {
+        self.capabilities
+            .as_ref()
+            .map(|caps| caps.values(INPUT_SOURCE_CODE))
+            .unwrap_or(&[])
     }
 }
 
@@ -83,6 +209,8 @@ impl AppState {
             mon.settings_expanded = false;
         }
 
+        self.save_brightness(true);
+
         if let Some(popup) = self.popup.take() {
             self.last_quit = Some((now(), popup.kind));
 
@@ -153,11 +281,21 @@ pub struct AppState {
     pub core: Core,
     popup: Option<Popup>,
     pub monitors: HashMap<DisplayId, MonitorState>,
+    outputs: HashMap<ObjectId, WaylandOutput>,
     pub theme_mode_config: ThemeMode,
-    sender: Option<Sender<EventToSub>>,
+    sender: Option<UnboundedSender<EventToSub>>,
     pub config: Config,
     config_handler: CosmicConfig,
     last_quit: Option<(u128, PopupKind)>,
+    /// The schedule stops after a manual change, until it's resumed from the popup
+    pub schedule_paused: bool,
+    /// Position of the sliders last set from the ambient light
+    ambient_brightness: Option<f32>,
+    /// Brightness of write-only monitors not saved yet, with the time of the last change
+    unsaved_brightness: HashMap<DisplayId, (u16, Instant)>,
+    /// Last level written to the internal panel, and when
+    backlight_written: Option<(f32, Instant)>,
+    follow_anchors: HashMap<DisplayId, FollowAnchor>,
 }
 
 #[derive(Clone, Debug)]
@@ -177,12 +315,47 @@ pub enum AppMsg {
     },
     ToggleMonSettings(DisplayId),
     SetMonGammaMap(DisplayId, f32),
+    /// Floor and ceiling of the hardware range, between 0 and 1
+    SetMonRange(DisplayId, f32, f32),
+    /// Use a custom curve instead of the gamma and the range
+    SetMonCurve(DisplayId, bool),
+    /// Move a control point of the custom curve
+    SetMonCurvePoint(DisplayId, usize, f32),
+    /// Move a monitor to a link group, a new one if the index is past the last group.
+    /// Unlinked if None.
+    SetMonLinkGroup(DisplayId, Option<usize>),
+    SetContrast(DisplayId, u16),
+    SetInputSource(DisplayId, u16),
 
     /// Send from the subscription
-    SubscriptionReady((HashMap<DisplayId, MonitorInfo>, Sender<EventToSub>)),
+    SubscriptionReady((HashMap<DisplayId, MonitorInfo>, UnboundedSender<EventToSub>)),
+    /// Send from the subscription
+    MonitorAdded(DisplayId, MonitorInfo),
+    /// Send from the subscription
+    MonitorRemoved(DisplayId),
+    /// Send from the subscription
+    MonitorStatusChanged(DisplayId, MonitorStatus),
+    RetryMonitor(DisplayId),
     /// Send from the subscription
     BrightnessWasUpdated(DisplayId, ScreenBrightness),
+    /// Send from the subscription
+    ContrastWasUpdated(DisplayId, u16),
+    /// Send from the subscription
+    InputSourceWasUpdated(DisplayId, u16),
     Refresh,
+    /// Periodic background read of the monitors
+    Poll,
+    SaveBrightness,
+    ScheduleTick,
+    SetSchedulePaused(bool),
+    /// Smoothed illuminance in lux
+    AmbientLight(f32),
+    SetAmbientLight(bool),
+    /// Previous and new brightness of the internal panel, between 0 and 1
+    InternalBrightnessChanged(f32, f32),
+    SetFollowInternal(bool),
+    OutputChanged(ObjectId, WaylandOutput),
+    OutputRemoved(ObjectId),
 }
 
 impl AppState {
@@ -194,6 +367,298 @@ impl AppState {
         }
     }
 
+    /// Write-only monitors can't report their brightness, so the value is saved
+    /// once it stops changing
+    fn set_brightness(&mut self, id: DisplayId, brightness: u16) {
+        let write_only = self.monitors.get(&id).is_some_and(|m| !m.is_confirmed());
+
+        if write_only {
+            self.unsaved_brightness
+                .insert(id.clone(), (brightness, Instant::now()));
+        }
+
+        if let Some(monitor) = self.monitors.get(&id).filter(|_| is_backlight(&id)) {
+            let level = brightness as f32 / monitor.max_brightness as f32;
+            self.backlight_written = Some((level, Instant::now()));
+        }
+
+        self.send(EventToSub::Set(id, brightness));
+    }
+
+    /// Save the brightness of the write-only monitors that didn't change for a while,
+    /// or all of them with `all`
+    fn save_brightness(&mut self, all: bool) {
+        let ready: Vec<_> = self
+            .unsaved_brightness
+            .iter()
+            .filter(|(_, (_, changed))| all || changed.elapsed() >= SAVE_DELAY)
+            .map(|(id, (brightness, _))| (id.clone(), *brightness))
+            .collect();
+
+        for (id, brightness) in ready {
+            self.unsaved_brightness.remove(&id);
+
+            if self.config.get_last_brightness(&id) != Some(brightness) {
+                self.update_monitor_config(id, |monitor| {
+                    monitor.last_brightness = Some(brightness);
+                });
+            }
+        }
+    }
+
+    /// Last brightness set on a write-only monitor
+    fn last_brightness(&self, id: &str) -> Option<u16> {
+        self.unsaved_brightness
+            .get(id)
+            .map(|(brightness, _)| *brightness)
+            .or_else(|| self.config.get_last_brightness(id))
+    }
+
+    /// The probe of a new write-only monitor wrote a default brightness, restore the saved one
+    fn restore_last_brightness(&self, id: &DisplayId, m: &MonitorInfo) {
+        if m.status != MonitorStatus::WriteOnly {
+            return;
+        }
+        if let Some(brightness) = self.last_brightness(id).filter(|b| *b != m.brightness) {
+            self.send(EventToSub::Set(id.clone(), brightness));
+        }
+    }
+
+    /// Move the slider of a monitor, and the sliders of the monitors linked to it
+    fn set_linked_slider_brightness(&mut self, id: DisplayId, slider_brightness: f32) {
+        let linked = self
+            .config
+            .link_group(&id)
+            .map(|group| group.follow(&id, slider_brightness))
+            .unwrap_or_default();
+
+        for (id, slider_brightness) in std::iter::once((id, slider_brightness)).chain(linked) {
+            self.set_slider_brightness(id, slider_brightness);
+        }
+    }
+
+    fn set_slider_brightness(&mut self, id: DisplayId, slider_brightness: f32) {
+        let Some(monitor) = self.monitors.get_mut(&id) else {
+            return;
+        };
+        if !monitor.is_available() {
+            return;
+        }
+
+        monitor.slider_brightness = slider_brightness;
+        let b = monitor.get_mapped_brightness(&self.config.get_monitor_config(&id));
+        self.set_brightness(id, b);
+    }
+
+    /// Move all the monitors to the brightness scheduled now,
+    /// unless the schedule is off, paused, or replaced by the ambient light
+    fn apply_schedule(&mut self) {
+        if !self.config.has_schedule() || self.schedule_paused {
+            return;
+        }
+
+        let now = Local::now();
+
+        let slider_brightness = match &self.config.sun_schedule {
+            Some(sun) => schedule::sun_brightness(sun, now.to_utc()),
+            None => match schedule::scheduled_brightness(&self.config.schedule, now.time()) {
+                Some(slider_brightness) => slider_brightness,
+                None => return,
+            },
+        };
+
+        self.set_all_slider_brightness(slider_brightness);
+    }
+
+    /// Move the sliders of all the external monitors.
+    /// The internal panel is left alone, the system manages its brightness.
+    ///
+    /// Only the first monitor of a link group is moved, the others keep their difference with it.
+    fn set_all_slider_brightness(&mut self, slider_brightness: f32) {
+        let mut ids: Vec<_> = self
+            .monitors
+            .keys()
+            .filter(|id| !is_backlight(id))
+            .cloned()
+            .collect();
+        ids.sort();
+
+        let mut moved = HashSet::new();
+
+        for id in ids {
+            if moved.contains(&id) {
+                continue;
+            }
+            if let Some(group) = self.config.link_group(&id) {
+                moved.extend(group.members.keys().cloned());
+            }
+
+            let unchanged = self
+                .monitors
+                .get(&id)
+                .is_some_and(|m| (m.slider_brightness - slider_brightness).abs() <= f32::EPSILON);
+            if !unchanged {
+                self.set_linked_slider_brightness(id, slider_brightness);
+            }
+        }
+    }
+
+    /// Whether a change of the internal panel was made by the applet,
+    /// e.g. with its slider, and must not be followed
+    fn is_own_backlight_change(&mut self, level: f32) -> bool {
+        let Some((written, at)) = self.backlight_written else {
+            return false;
+        };
+
+        if (written - level).abs() < LEVEL_TOLERANCE {
+            // the last write landed, later changes come from elsewhere
+            self.backlight_written = None;
+            return true;
+        }
+
+        // values written before the last one, e.g. while dragging the slider
+        let transition = self.config.worker_settings().transition;
+        at.elapsed() < BACKLIGHT_ECHO_DELAY + transition.unwrap_or_default()
+    }
+
+    /// Apply the relative change of the internal panel to the sliders of the external monitors
+    fn follow_internal_brightness(&mut self, previous: f32, level: f32) {
+        let targets: Vec<_> = self
+            .monitors
+            .iter()
+            .filter(|(id, m)| !is_backlight(id) && m.is_available())
+            .map(|(id, monitor)| {
+                let anchor = match self.follow_anchors.get(id) {
+                    Some(anchor)
+                        if anchor.level > 0.0
+                            && (anchor.followed - monitor.slider_brightness).abs()
+                                < LEVEL_TOLERANCE =>
+                    {
+                        *anchor
+                    }
+                    // moved by something else, start again from its current position
+                    _ => FollowAnchor {
+                        level: previous,
+                        slider_brightness: monitor.slider_brightness,
+                        followed: monitor.slider_brightness,
+                    },
+                };
+
+                let followed = if anchor.level > 0.0 {
+                    anchor.slider_brightness * level / anchor.level
+                } else {
+                    level
+                };
+
+                let anchor = FollowAnchor {
+                    followed: followed.clamp(0.0, 1.0),
+                    ..anchor
+                };
+                (id.clone(), anchor)
+            })
+            .collect();
+
+        for (id, anchor) in targets {
+            self.set_slider_brightness(id.clone(), anchor.followed);
+            self.follow_anchors.insert(id, anchor);
+        }
+    }
+
+    /// A manual change overrides the schedule
+    fn pause_schedule(&mut self) {
+        if self.config.has_schedule() && !self.schedule_paused {
+            info!("schedule paused by a manual change");
+            self.schedule_paused = true;
+        }
+    }
+
+    fn monitor_state(&self, id: &str, m: MonitorInfo) -> MonitorState {
+        let monitor_config = self.config.get_monitor_config(id);
+
+        let brightness = match m.status {
+            // the value written by the probe, until the saved one is restored
+            MonitorStatus::WriteOnly => self.last_brightness(id).unwrap_or(m.brightness),
+            _ => m.brightness,
+        };
+
+        MonitorState {
+            name: m.name,
+            slider_brightness: get_slider_brightness(brightness, &monitor_config, m.max_brightness),
+            max_brightness: m.max_brightness,
+            contrast: m.contrast,
+            max_contrast: m.max_contrast,
+            input_source: m.input_source,
+            capabilities: m.capabilities,
+            status: m.status,
+            settings_expanded: false,
+            output: self.find_output(m.connector.as_deref()),
+            connector: m.connector,
+        }
+    }
+
+    fn find_output(&self, connector: Option<&str>) -> Option<WaylandOutput> {
+        let connector = connector?;
+        self.outputs
+            .values()
+            .find(|output| output.connector == connector)
+            .cloned()
+    }
+
+    /// Match the monitors with the Wayland outputs again, after an output changed
+    fn link_outputs(&mut self) {
+        let outputs: Vec<_> = self
+            .monitors
+            .values()
+            .map(|monitor| self.find_output(monitor.connector.as_deref()))
+            .collect();
+
+        for (monitor, output) in self.monitors.values_mut().zip(outputs) {
+            monitor.output = output;
+        }
+    }
+
+    /// Monitors ordered from left to right, as placed in the compositor.
+    /// Monitors without a Wayland output come last.
+    pub fn sorted_monitors(&self) -> Vec<(&DisplayId, &MonitorState)> {
+        let mut monitors: Vec<_> = self.monitors.iter().collect();
+
+        monitors.sort_by_key(|(id, monitor)| {
+            (
+                monitor.output.is_none(),
+                monitor.output.as_ref().map(|output| output.position),
+                *id,
+            )
+        });
+
+        monitors
+    }
+
+    /// Move the config of a monitor stored by an old version under its legacy id
+    fn migrate_monitor_config(&mut self, legacy_id: &str, id: &str) {
+        if self.config.monitors.contains_key(id) || !self.config.monitors.contains_key(legacy_id) {
+            return;
+        }
+
+        info!("migrate config of {legacy_id} to {id}");
+
+        let mut monitors = self.config.monitors.clone();
+        if let Some(monitor) = monitors.remove(legacy_id) {
+            monitors.insert(id.to_owned(), monitor);
+        }
+
+        if let Err(e) = self.config.set_monitors(&self.config_handler, monitors) {
+            error!("can't write config: {e}");
+        }
+    }
+
+    /// Write the brightness of the slider again, after its mapping changed
+    fn apply_monitor_config(&mut self, id: DisplayId) {
+        if let Some(monitor) = self.monitors.get(&id) {
+            let b = monitor.get_mapped_brightness(&self.config.get_monitor_config(&id));
+            self.set_brightness(id, b);
+        }
+    }
+
     fn update_monitor_config(&mut self, id: String, f: impl Fn(&mut MonitorConfig)) {
         let mut monitors = std::mem::take(&mut self.config.monitors);
 
@@ -227,9 +692,15 @@ impl cosmic::Application for AppState {
             config: flags.1,
             popup: None,
             monitors: HashMap::new(),
+            outputs: HashMap::new(),
             theme_mode_config: ThemeMode::default(),
             sender: None,
             last_quit: None,
+            schedule_paused: false,
+            ambient_brightness: None,
+            unsaved_brightness: HashMap::new(),
+            backlight_written: None,
+            follow_anchors: HashMap::new(),
         };
 
         (window, Task::none())
@@ -256,38 +727,42 @@ impl cosmic::Application for AppState {
             AppMsg::ToggleQuickSettings => return self.toggle_popup(PopupKind::QuickSettings),
             AppMsg::ClosePopup => return self.close_popup(),
             AppMsg::SetScreenBrightness(id, slider_brightness) => {
-                if let Some(monitor) = self.monitors.get_mut(&id) {
-                    monitor.slider_brightness = slider_brightness;
-                    let gamma = self.config.get_gamma_map(&id);
-                    let b = monitor.get_mapped_brightness(gamma);
-                    self.send(EventToSub::Set(id, b));
-                }
+                self.pause_schedule();
+                self.set_linked_slider_brightness(id, slider_brightness);
             }
             AppMsg::ChangeGlobalBrightness { delta } => {
+                self.pause_schedule();
+
                 let mut vec = Vec::with_capacity(self.monitors.len());
 
                 for (id, monitor) in self.monitors.iter_mut() {
-                    monitor.slider_brightness = (monitor.slider_brightness + delta).clamp(0.0, 1.0);
+                    if !monitor.is_available() {
+                        continue;
+                    }
+                    // the external monitors follow it, moving it would move them twice
+                    if self.config.follows_internal() && is_backlight(id) {
+                        continue;
+                    }
 
-                    let gamma = self.config.get_gamma_map(id);
+                    monitor.slider_brightness = (monitor.slider_brightness + delta).clamp(0.0, 1.0);
 
-                    let b = monitor.get_mapped_brightness(gamma);
+                    let b = monitor.get_mapped_brightness(&self.config.get_monitor_config(id));
 
-                    vec.push(EventToSub::Set(id.clone(), b));
+                    vec.push((id.clone(), b));
                 }
 
-                for e in vec {
-                    self.send(e);
+                for (id, b) in vec {
+                    self.set_brightness(id, b);
                 }
             }
             AppMsg::ToggleMinMaxBrightness(id) => {
-                if let Some(monitor) = self.monitors.get_mut(&id) {
-                    let new_val = match monitor.slider_brightness {
-                        x if x < 0.5 => 100,
-                        _ => 0,
+                self.pause_schedule();
+                if let Some(monitor) = self.monitors.get(&id) {
+                    let slider_brightness = match monitor.slider_brightness {
+                        x if x < 0.5 => 1.0,
+                        _ => 0.0,
                     };
-                    monitor.slider_brightness = new_val as f32 / 100.0;
-                    self.send(EventToSub::Set(id, new_val));
+                    self.set_linked_slider_brightness(id, slider_brightness);
                 }
             }
             AppMsg::ThemeModeConfigChanged(config) => {
@@ -322,46 +797,243 @@ impl cosmic::Application for AppState {
                 }
             }
             AppMsg::SubscriptionReady((monitors, sender)) => {
+                for (id, m) in &monitors {
+                    if let Some(legacy_id) = &m.legacy_id {
+                        self.migrate_monitor_config(legacy_id, id);
+                    }
+                }
+
+                self.sender.replace(sender);
+                self.send(EventToSub::Settings(self.config.worker_settings()));
+
+                for (id, m) in &monitors {
+                    self.restore_last_brightness(id, m);
+                }
+
                 self.monitors = monitors
                     .into_iter()
                     .map(|(id, m)| {
-                        let gamma_map = self.config.get_gamma_map(&id);
-                        (
-                            id,
-                            MonitorState {
-                                name: m.name,
-                                slider_brightness: get_slider_brightness(m.brightness, gamma_map),
-                                settings_expanded: false,
-                            },
-                        )
+                        let monitor = self.monitor_state(&id, m);
+                        (id, monitor)
                     })
                     .collect();
 
-                self.sender.replace(sender);
+                self.apply_schedule();
+            }
+            AppMsg::MonitorAdded(id, m) => {
+                if let Some(legacy_id) = &m.legacy_id {
+                    self.migrate_monitor_config(legacy_id, &id);
+                }
+                self.restore_last_brightness(&id, &m);
+                let monitor = self.monitor_state(&id, m);
+                self.monitors.insert(id, monitor);
+            }
+            AppMsg::MonitorRemoved(id) => {
+                self.monitors.remove(&id);
+            }
+            AppMsg::MonitorStatusChanged(id, status) => {
+                if let Some(monitor) = self.monitors.get_mut(&id) {
+                    monitor.status = status;
+                }
+            }
+            AppMsg::RetryMonitor(id) => {
+                self.send(EventToSub::Retry(id));
             }
             AppMsg::BrightnessWasUpdated(id, brightness) => {
                 if let Some(monitor) = self.monitors.get_mut(&id) {
-                    monitor.set_slider_brightness(brightness, self.config.get_gamma_map(&id));
+                    monitor.set_slider_brightness(brightness, &self.config.get_monitor_config(&id));
                 }
             }
-            AppMsg::SetMonGammaMap(id, gamma) => {
-                if let Some(monitor) = self.monitors.get(&id) {
-                    let b = monitor.get_mapped_brightness(gamma);
-                    self.send(EventToSub::Set(id.clone(), b));
+            AppMsg::ContrastWasUpdated(id, contrast) => {
+                if let Some(monitor) = self.monitors.get_mut(&id) {
+                    monitor.contrast = Some(contrast);
                 }
-
-                self.update_monitor_config(id, |monitor| {
+            }
+            AppMsg::SetContrast(id, contrast) => {
+                if let Some(monitor) = self.monitors.get_mut(&id) {
+                    monitor.contrast = Some(contrast);
+                    self.send(EventToSub::SetContrast(id, contrast));
+                }
+            }
+            AppMsg::InputSourceWasUpdated(id, input_source) => {
+                if let Some(monitor) = self.monitors.get_mut(&id) {
+                    monitor.input_source = Some(input_source);
+                }
+            }
+            AppMsg::SetInputSource(id, input_source) => {
+                if let Some(monitor) = self.monitors.get_mut(&id) {
+                    monitor.input_source = Some(input_source);
+                    self.send(EventToSub::SetInputSource(id, input_source));
+                }
+            }
+            AppMsg::SetMonGammaMap(id, gamma) => {
+                self.update_monitor_config(id.clone(), |monitor| {
                     monitor.gamma_map = gamma;
                 });
+                self.apply_monitor_config(id);
+            }
+            AppMsg::SetMonRange(id, floor, ceiling) => {
+                // keep some room between them, so the slider still does something
+                let floor = floor.clamp(0.0, 1.0 - MIN_RANGE);
+                let ceiling = ceiling.clamp(floor + MIN_RANGE, 1.0);
+
+                self.update_monitor_config(id.clone(), |monitor| {
+                    monitor.floor = floor;
+                    monitor.ceiling = ceiling;
+                });
+                self.apply_monitor_config(id);
+            }
+            AppMsg::SetMonCurve(id, enabled) => {
+                self.update_monitor_config(id.clone(), |monitor| {
+                    // start from the current mapping, so the brightness doesn't jump
+                    let curve =
+                        enabled.then(|| Curve::sample(&CURVE_POINTS, |x| gamma_level(x, monitor)));
+                    monitor.curve = curve;
+                });
+                self.apply_monitor_config(id);
+            }
+            AppMsg::SetMonCurvePoint(id, index, y) => {
+                self.update_monitor_config(id.clone(), |monitor| {
+                    if let Some(curve) = &mut monitor.curve {
+                        curve.set_y(index, y);
+                    }
+                });
+                self.apply_monitor_config(id);
+            }
+            AppMsg::SetMonLinkGroup(id, index) => {
+                let mut groups = self.config.link_groups.clone();
+
+                for group in &mut groups {
+                    group.members.remove(&id);
+                }
+
+                if let Some(index) = index {
+                    let slider = self
+                        .monitors
+                        .get(&id)
+                        .map(|m| m.slider_brightness)
+                        .unwrap_or_default();
+
+                    match groups.get_mut(index) {
+                        Some(group) => {
+                            // keep the current difference with the group
+                            let level = group
+                                .members
+                                .iter()
+                                .find_map(|(member, link)| {
+                                    let monitor = self.monitors.get(member)?;
+                                    Some(link.level(monitor.slider_brightness))
+                                })
+                                .unwrap_or(slider);
+                            group.members.insert(
+                                id,
+                                Link {
+                                    offset: slider - level,
+                                },
+                            );
+                        }
+                        None => groups.push(LinkGroup {
+                            members: HashMap::from([(id, Link { offset: 0. })]),
+                        }),
+                    }
+                }
+
+                groups.retain(|group| !group.members.is_empty());
+
+                if let Err(e) = self.config.set_link_groups(&self.config_handler, groups) {
+                    error!("can't write config: {e}");
+                }
             }
             AppMsg::ToggleMonSettings(id) => {
                 if let Some(mon) = self.monitors.get_mut(&id) {
                     mon.settings_expanded = !mon.settings_expanded;
                 }
             }
-            AppMsg::ConfigChanged(config) => self.config = config,
+            AppMsg::ConfigChanged(config) => {
+                if config.worker_settings() != self.config.worker_settings() {
+                    self.send(EventToSub::Settings(config.worker_settings()));
+                }
+                self.config = config;
+            }
             AppMsg::Refresh => {
-                self.send(EventToSub::Refresh);
+                // monitors that were asleep at login are only found by a new enumeration
+                self.send(EventToSub::Rescan);
+            }
+            AppMsg::Poll => {
+                self.send(EventToSub::Poll);
+            }
+            AppMsg::SaveBrightness => self.save_brightness(false),
+            AppMsg::ScheduleTick => self.apply_schedule(),
+            AppMsg::SetSchedulePaused(paused) => {
+                self.schedule_paused = paused;
+                self.apply_schedule();
+            }
+            AppMsg::AmbientLight(lux) => {
+                let slider_brightness = self.config.ambient_curve().eval(lux).clamp(0.0, 1.0);
+
+                if self
+                    .ambient_brightness
+                    .is_some_and(|last| (last - slider_brightness).abs() < AMBIENT_HYSTERESIS)
+                {
+                    return Task::none();
+                }
+
+                debug!("{lux} lux, set brightness to {slider_brightness}");
+                self.ambient_brightness = Some(slider_brightness);
+
+                self.set_all_slider_brightness(slider_brightness);
+            }
+            AppMsg::InternalBrightnessChanged(previous, level) => {
+                if self.is_own_backlight_change(level) {
+                    return Task::none();
+                }
+
+                for (id, monitor) in &mut self.monitors {
+                    if is_backlight(id) {
+                        let brightness = (level * monitor.max_brightness as f32).round() as u16;
+                        monitor
+                            .set_slider_brightness(brightness, &self.config.get_monitor_config(id));
+                    }
+                }
+                self.follow_internal_brightness(previous, level);
+            }
+            AppMsg::SetFollowInternal(enabled) => {
+                self.follow_anchors.clear();
+
+                if let Err(e) = self
+                    .config
+                    .set_follow_internal(&self.config_handler, enabled)
+                {
+                    error!("can't write config: {e}");
+                }
+                // both would move the monitors, only one drives them
+                if enabled && self.config.ambient_light {
+                    self.ambient_brightness = None;
+                    if let Err(e) = self.config.set_ambient_light(&self.config_handler, false) {
+                        error!("can't write config: {e}");
+                    }
+                }
+            }
+            AppMsg::SetAmbientLight(enabled) => {
+                self.ambient_brightness = None;
+
+                if let Err(e) = self.config.set_ambient_light(&self.config_handler, enabled) {
+                    error!("can't write config: {e}");
+                }
+                if enabled && self.config.follow_internal {
+                    self.follow_anchors.clear();
+                    if let Err(e) = self.config.set_follow_internal(&self.config_handler, false) {
+                        error!("can't write config: {e}");
+                    }
+                }
+            }
+            AppMsg::OutputChanged(id, output) => {
+                self.outputs.insert(id, output);
+                self.link_outputs();
+            }
+            AppMsg::OutputRemoved(id) => {
+                self.outputs.remove(&id);
+                self.link_outputs();
             }
         }
         Task::none()
@@ -389,13 +1061,102 @@ impl cosmic::Application for AppState {
     }
 
     fn subscription(&self) -> Subscription<Self::Message> {
-        Subscription::batch(vec![
+        let mut subscriptions = vec![
             self.core
                 .watch_config(THEME_MODE_ID)
                 .map(|u| AppMsg::ThemeModeConfigChanged(u.config)),
             Subscription::run(monitor::sub),
             config::sub(),
+            output::sub(),
             // Subscription::run(refresh_sub),
-        ])
+        ];
+
+        if self.config.has_schedule() && !self.schedule_paused {
+            subscriptions.push(schedule::sub());
+        }
+
+        if self.config.ambient_light {
+            subscriptions.push(ambient::sub());
+        }
+
+        if self.config.follows_internal() {
+            subscriptions.push(follow::sub());
+        }
+
+        if let Some(interval) = self.config.get_poll_interval(self.popup.is_some()) {
+            subscriptions.push(time::every(interval).map(|_| AppMsg::Poll));
+        }
+
+        if !self.unsaved_brightness.is_empty() {
+            subscriptions.push(time::every(SAVE_DELAY).map(|_| AppMsg::SaveBrightness));
+        }
+
+        Subscription::batch(subscriptions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor_config(gamma_map: f32, floor: f32, ceiling: f32) -> MonitorConfig {
+        MonitorConfig {
+            gamma_map,
+            floor,
+            ceiling,
+            ..MonitorConfig::new()
+        }
+    }
+
+    fn assert_round_trip(config: &MonitorConfig, max_brightness: u16, tolerance: f32) {
+        for i in 0..=100 {
+            let slider = i as f32 / 100.0;
+            let brightness = get_mapped_brightness(slider, config, max_brightness);
+            let back = get_slider_brightness(brightness, config, max_brightness);
+
+            assert!(
+                (back - slider).abs() <= tolerance,
+                "{slider} -> {brightness} -> {back} with {config:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_round_trip(&monitor_config(1.0, 0.0, 1.0), 100, 0.001);
+        assert_round_trip(&monitor_config(1.0, 0.2, 0.8), 1000, 0.001);
+        assert_round_trip(&monitor_config(1.0, 0.2, 0.8), 100, 0.01);
+        assert_round_trip(&monitor_config(1.5, 0.1, 0.9), 10000, 0.005);
+    }
+
+    #[test]
+    fn round_trip_with_curve() {
+        let config = MonitorConfig {
+            curve: Some(Curve::from(vec![(0.0, 0.1), (0.5, 0.3), (1.0, 0.9)])),
+            ..MonitorConfig::new()
+        };
+
+        assert_round_trip(&config, 1000, 0.002);
+    }
+
+    #[test]
+    fn floor_and_ceiling() {
+        let config = monitor_config(1.0, 0.2, 0.8);
+
+        assert_eq!(get_mapped_brightness(0.0, &config, 100), 20);
+        assert_eq!(get_mapped_brightness(0.5, &config, 100), 50);
+        assert_eq!(get_mapped_brightness(1.0, &config, 100), 80);
+
+        // set with the buttons of the monitor, outside of the range
+        assert_eq!(get_slider_brightness(10, &config, 100), 0.0);
+        assert_eq!(get_slider_brightness(90, &config, 100), 1.0);
+    }
+
+    #[test]
+    fn empty_range() {
+        let config = monitor_config(1.0, 0.5, 0.5);
+
+        assert_eq!(get_mapped_brightness(1.0, &config, 100), 50);
+        assert_eq!(get_slider_brightness(50, &config, 100), 0.0);
     }
 }
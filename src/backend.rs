@@ -0,0 +1,87 @@
+use crate::monitor::DisplayId;
+
+mod backlight;
+mod ddc;
+mod drm;
+mod simulated;
+
+pub use backlight::{
+    BACKLIGHT_ROOT, BacklightBackend, backlight_level, find_backlight, is_backlight,
+};
+pub use ddc::DdcBackend;
+pub use simulated::{SimulatedBackend, SimulatedMonitor};
+
+/// Env var used to replace the DDC backend with simulated monitors.
+/// The value is the number of monitors to simulate.
+const SIMULATE_ENV: &str = "EXTERNAL_MONITOR_BRIGHTNESS_SIMULATE";
+
+/// Env var used to read the internal panel backlight from another directory than sysfs
+const BACKLIGHT_ROOT_ENV: &str = "EXTERNAL_MONITOR_BRIGHTNESS_BACKLIGHT_ROOT";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VcpValue {
+    pub value: u16,
+    pub maximum: u16,
+}
+
+/// Source of displays for the monitor subscription.
+pub trait Backend: Send {
+    fn enumerate(&mut self) -> Vec<Box<dyn DisplayHandle>>;
+}
+
+/// A display that can be controlled with VCP features.
+///
+/// All methods are blocking.
+pub trait DisplayHandle: Send {
+    /// Stable across reboots and ports, used to store the settings of the display
+    fn id(&self) -> DisplayId;
+    /// Id used by previous versions, to migrate the settings of the display
+    fn legacy_id(&self) -> Option<DisplayId> {
+        None
+    }
+    fn name(&self) -> String;
+    /// Name of the DRM connector of the display, e.g. `DP-2`
+    fn connector(&self) -> Option<String> {
+        None
+    }
+    fn get_vcp_feature(&mut self, code: u8) -> anyhow::Result<VcpValue>;
+    fn set_vcp_feature(&mut self, code: u8, value: u16) -> anyhow::Result<()>;
+    fn capabilities_string(&mut self) -> anyhow::Result<String>;
+}
+
+/// Displays of several backends, e.g. external monitors and the internal panel
+pub struct CombinedBackend(pub Vec<Box<dyn Backend>>);
+
+impl Backend for CombinedBackend {
+    fn enumerate(&mut self) -> Vec<Box<dyn DisplayHandle>> {
+        self.0
+            .iter_mut()
+            .flat_map(|backend| backend.enumerate())
+            .collect()
+    }
+}
+
+/// Directory of the backlight interfaces, sysfs unless overridden for testing
+pub fn backlight_root() -> String {
+    std::env::var(BACKLIGHT_ROOT_ENV).unwrap_or_else(|_| BACKLIGHT_ROOT.to_owned())
+}
+
+pub fn default_backend() -> Box<dyn Backend> {
+    match std::env::var(SIMULATE_ENV)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+    {
+        Some(count) => {
+            info!("using {count} simulated monitors");
+            Box::new(SimulatedBackend::new(
+                (1..=count)
+                    .map(|i| SimulatedMonitor::new(format!("sim-{i}"), format!("Simulated {i}")))
+                    .collect(),
+            ))
+        }
+        None => Box::new(CombinedBackend(vec![
+            Box::new(DdcBackend),
+            Box::new(BacklightBackend::new(backlight_root())),
+        ])),
+    }
+}
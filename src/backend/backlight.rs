@@ -0,0 +1,334 @@
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, bail};
+
+use crate::fl;
+use crate::monitor::{BRIGHTNESS_CODE, DisplayId};
+
+use super::{Backend, DisplayHandle, VcpValue, drm};
+
+pub const BACKLIGHT_ROOT: &str = "/sys/class/backlight";
+
+/// Prefix of the id of backlight displays, to not collide with DDC ids
+const BACKLIGHT_ID_PREFIX: &str = "backlight/";
+
+/// Internal panel of laptops, controlled with the kernel backlight interface.
+///
+/// The root is `/sys/class/backlight` on a real system,
+/// but any directory with the same layout can be used.
+pub struct BacklightBackend {
+    root: PathBuf,
+}
+
+impl BacklightBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl Backend for BacklightBackend {
+    fn enumerate(&mut self) -> Vec<Box<dyn DisplayHandle>> {
+        find_backlight(&self.root)
+            .map(|path| Box::new(Backlight { path, logind: None }) as Box<dyn DisplayHandle>)
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Directory of the backlight of the internal panel
+pub fn find_backlight(root: &Path) -> Option<PathBuf> {
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) => {
+            if e.kind() != ErrorKind::NotFound {
+                warn!("can't read {}: {e}", root.display());
+            }
+            return None;
+        }
+    };
+
+    // Like systemd, only use the best interface when there are several for the same panel
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.join("max_brightness").exists())
+        .min_by_key(|path| {
+            let kind = fs::read_to_string(path.join("type")).unwrap_or_default();
+            match kind.trim() {
+                "firmware" => 0,
+                "platform" => 1,
+                "raw" => 2,
+                _ => 3,
+            }
+        })
+}
+
+/// Brightness applied by the hardware, between 0 and 1
+pub fn backlight_level(path: &Path) -> anyhow::Result<f32> {
+    let max = read_u32(&path.join("max_brightness"))?;
+    if max == 0 {
+        bail!("max_brightness of {} is 0", path.display());
+    }
+    let value = read_u32(&path.join("actual_brightness"))
+        .or_else(|_| read_u32(&path.join("brightness")))?;
+
+    Ok(value.min(max) as f32 / max as f32)
+}
+
+/// Whether the display is the internal panel
+pub fn is_backlight(id: &str) -> bool {
+    id.starts_with(BACKLIGHT_ID_PREFIX)
+}
+
+struct Backlight {
+    path: PathBuf,
+    /// Opened on the first write refused by sysfs
+    logind: Option<zbus::blocking::Connection>,
+}
+
+impl Backlight {
+    fn device_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    /// VCP values are u16 but some backlights go way beyond,
+    /// e.g. 96000 for intel_backlight
+    fn scale(max_brightness: u32) -> u32 {
+        max_brightness.div_ceil(u16::MAX as u32).max(1)
+    }
+
+    fn max_brightness(&self) -> anyhow::Result<u32> {
+        let max = read_u32(&self.path.join("max_brightness"))?;
+        if max == 0 {
+            bail!("max_brightness of {} is 0", self.path.display());
+        }
+        Ok(max)
+    }
+
+    fn write_brightness(&mut self, value: u32) -> anyhow::Result<()> {
+        let path = self.path.join("brightness");
+
+        match fs::write(&path, value.to_string()) {
+            Ok(()) => Ok(()),
+            // sysfs is only writable by root without an udev rule, ask logind instead
+            Err(e) if e.kind() == ErrorKind::PermissionDenied => self
+                .logind_set_brightness(value)
+                .with_context(|| format!("can't write {}: {e}", path.display())),
+            Err(e) => Err(e).with_context(|| format!("can't write {}", path.display())),
+        }
+    }
+
+    fn logind_set_brightness(&mut self, value: u32) -> anyhow::Result<()> {
+        let name = self.device_name();
+        let connection = match self.logind.take() {
+            Some(connection) => connection,
+            None => zbus::blocking::Connection::system()?,
+        };
+
+        connection.call_method(
+            Some("org.freedesktop.login1"),
+            "/org/freedesktop/login1/session/auto",
+            Some("org.freedesktop.login1.Session"),
+            "SetBrightness",
+            &("backlight", name.as_str(), value),
+        )?;
+
+        // dropped on errors, to connect again if the bus was restarted
+        self.logind = Some(connection);
+        Ok(())
+    }
+}
+
+impl DisplayHandle for Backlight {
+    fn id(&self) -> DisplayId {
+        format!("{BACKLIGHT_ID_PREFIX}{}", self.device_name())
+    }
+
+    fn name(&self) -> String {
+        fl!("builtin_display")
+    }
+
+    fn connector(&self) -> Option<String> {
+        drm::internal_connector()
+    }
+
+    fn get_vcp_feature(&mut self, code: u8) -> anyhow::Result<VcpValue> {
+        if code != BRIGHTNESS_CODE {
+            bail!("unsupported VCP code {code:#04x}");
+        }
+
+        let max = self.max_brightness()?;
+        // actual_brightness is the value really applied by the hardware
+        let value = read_u32(&self.path.join("actual_brightness"))
+            .or_else(|_| read_u32(&self.path.join("brightness")))?;
+        let scale = Self::scale(max);
+
+        Ok(VcpValue {
+            value: (value.min(max) / scale) as u16,
+            maximum: (max / scale) as u16,
+        })
+    }
+
+    fn set_vcp_feature(&mut self, code: u8, value: u16) -> anyhow::Result<()> {
+        if code != BRIGHTNESS_CODE {
+            bail!("unsupported VCP code {code:#04x}");
+        }
+
+        let max = self.max_brightness()?;
+        // 0 turns off the panel on most laptops
+        let value = (value as u32 * Self::scale(max)).clamp(1, max);
+
+        self.write_brightness(value)
+    }
+
+    fn capabilities_string(&mut self) -> anyhow::Result<String> {
+        Ok(String::from("(prot(backlight)type(lcd)vcp(10))"))
+    }
+}
+
+fn read_u32(path: &Path) -> anyhow::Result<u32> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("can't read {}", path.display()))?;
+
+    content
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid value in {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn add_backlight(root: &TempDir, name: &str, kind: &str, max: u32, brightness: u32) {
+        root.write(&format!("{name}/type"), kind);
+        root.write(&format!("{name}/max_brightness"), &max.to_string());
+        root.write(&format!("{name}/brightness"), &brightness.to_string());
+        root.write(
+            &format!("{name}/actual_brightness"),
+            &brightness.to_string(),
+        );
+    }
+
+    fn handle(root: &TempDir) -> Box<dyn DisplayHandle> {
+        BacklightBackend::new(root.path())
+            .enumerate()
+            .pop()
+            .unwrap()
+    }
+
+    #[test]
+    fn prefers_firmware_then_platform_then_raw() {
+        let root = TempDir::new();
+        add_backlight(&root, "intel_backlight", "raw", 100, 50);
+        add_backlight(&root, "thinkpad_screen", "platform", 100, 50);
+        assert_eq!(handle(&root).id(), "backlight/thinkpad_screen");
+
+        add_backlight(&root, "acpi_video0", "firmware", 100, 50);
+        assert_eq!(handle(&root).id(), "backlight/acpi_video0");
+    }
+
+    #[test]
+    fn ignores_directories_without_max_brightness() {
+        let root = TempDir::new();
+        root.write("acpi_video0/type", "firmware");
+        add_backlight(&root, "intel_backlight", "raw", 100, 50);
+
+        assert_eq!(handle(&root).id(), "backlight/intel_backlight");
+    }
+
+    #[test]
+    fn no_backlight() {
+        let root = TempDir::new();
+        assert!(BacklightBackend::new(root.path()).enumerate().is_empty());
+
+        let missing = root.path().join("missing");
+        assert!(BacklightBackend::new(missing).enumerate().is_empty());
+    }
+
+    #[test]
+    fn scales_large_maximums() {
+        let root = TempDir::new();
+        add_backlight(&root, "intel_backlight", "raw", 96000, 48000);
+        let mut handle = handle(&root);
+
+        let value = handle.get_vcp_feature(BRIGHTNESS_CODE).unwrap();
+        assert_eq!(
+            value,
+            VcpValue {
+                value: 24000,
+                maximum: 48000
+            }
+        );
+
+        handle.set_vcp_feature(BRIGHTNESS_CODE, 48000).unwrap();
+        assert_eq!(root.read("intel_backlight/brightness"), "96000");
+    }
+
+    #[test]
+    fn small_maximums_are_not_scaled() {
+        let root = TempDir::new();
+        add_backlight(&root, "acpi_video0", "firmware", 15, 7);
+        let mut handle = handle(&root);
+
+        let value = handle.get_vcp_feature(BRIGHTNESS_CODE).unwrap();
+        assert_eq!(
+            value,
+            VcpValue {
+                value: 7,
+                maximum: 15
+            }
+        );
+
+        handle.set_vcp_feature(BRIGHTNESS_CODE, 20).unwrap();
+        assert_eq!(root.read("acpi_video0/brightness"), "15");
+    }
+
+    #[test]
+    fn never_writes_zero() {
+        let root = TempDir::new();
+        add_backlight(&root, "intel_backlight", "raw", 100, 50);
+        let mut handle = handle(&root);
+
+        handle.set_vcp_feature(BRIGHTNESS_CODE, 0).unwrap();
+        assert_eq!(root.read("intel_backlight/brightness"), "1");
+    }
+
+    #[test]
+    fn falls_back_to_brightness() {
+        let root = TempDir::new();
+        add_backlight(&root, "intel_backlight", "raw", 100, 50);
+        let mut handle = handle(&root);
+
+        // the hardware applied another value than the one requested
+        root.write("intel_backlight/actual_brightness", "40");
+        assert_eq!(handle.get_vcp_feature(BRIGHTNESS_CODE).unwrap().value, 40);
+
+        fs::remove_file(root.path().join("intel_backlight/actual_brightness")).unwrap();
+        assert_eq!(handle.get_vcp_feature(BRIGHTNESS_CODE).unwrap().value, 50);
+        assert_eq!(
+            backlight_level(&root.path().join("intel_backlight")).unwrap(),
+            0.5
+        );
+    }
+
+    #[test]
+    fn only_brightness_is_supported() {
+        let root = TempDir::new();
+        add_backlight(&root, "intel_backlight", "raw", 100, 50);
+        let mut handle = handle(&root);
+
+        assert!(handle.get_vcp_feature(0x12).is_err());
+        assert!(handle.set_vcp_feature(0x12, 50).is_err());
+        assert!(is_backlight(&handle.id()));
+        assert!(!is_backlight("GSM-5B09-123"));
+    }
+}
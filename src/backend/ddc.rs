@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use ddc_hi::{Ddc, Display, DisplayInfo};
+
+use crate::monitor::DisplayId;
+
+use super::{Backend, DisplayHandle, VcpValue, drm};
+
+pub struct DdcBackend;
+
+impl Backend for DdcBackend {
+    fn enumerate(&mut self) -> Vec<Box<dyn DisplayHandle>> {
+        let displays = Display::enumerate();
+
+        let mut count = HashMap::new();
+        for display in &displays {
+            if let Some(id) = stable_id(&display.info) {
+                *count.entry(id).or_insert(0) += 1;
+            }
+        }
+
+        displays
+            .into_iter()
+            .map(|display| {
+                let id = match stable_id(&display.info) {
+                    // identical monitors without serial number, only the bus can tell them apart
+                    Some(id) if count[&id] > 1 => format!("{id}@{}", display.info.id),
+                    Some(id) => id,
+                    None => display.info.id.clone(),
+                };
+
+                Box::new(DdcDisplay { id, display }) as Box<dyn DisplayHandle>
+            })
+            .collect()
+    }
+}
+
+/// Id built from the EDID, which doesn't change when the monitor is plugged on another port
+fn stable_id(info: &DisplayInfo) -> Option<DisplayId> {
+    let manufacturer = info.manufacturer_id.as_deref()?;
+    let model = info.model_id?;
+
+    let serial = info
+        .serial_number
+        .as_deref()
+        .map(str::trim)
+        .filter(|serial| !serial.is_empty())
+        .map(str::to_owned)
+        .or_else(|| {
+            info.serial
+                .filter(|serial| *serial != 0)
+                .map(|s| s.to_string())
+        });
+
+    Some(match serial {
+        Some(serial) => format!("{manufacturer}-{model:04X}-{serial}"),
+        None => format!("{manufacturer}-{model:04X}"),
+    })
+}
+
+struct DdcDisplay {
+    id: DisplayId,
+    display: Display,
+}
+
+impl DisplayHandle for DdcDisplay {
+    fn id(&self) -> DisplayId {
+        self.id.clone()
+    }
+
+    fn legacy_id(&self) -> Option<DisplayId> {
+        Some(self.display.info.id.clone()).filter(|legacy_id| *legacy_id != self.id)
+    }
+
+    fn name(&self) -> String {
+        self.display.info.model_name.clone().unwrap_or_default()
+    }
+
+    fn connector(&self) -> Option<String> {
+        drm::connector_for_edid(self.display.info.edid_data.as_deref()?)
+    }
+
+    fn get_vcp_feature(&mut self, code: u8) -> anyhow::Result<VcpValue> {
+        let value = self.display.handle.get_vcp_feature(code)?;
+        Ok(VcpValue {
+            value: value.value(),
+            maximum: value.maximum(),
+        })
+    }
+
+    fn set_vcp_feature(&mut self, code: u8, value: u16) -> anyhow::Result<()> {
+        self.display.handle.set_vcp_feature(code, value)?;
+        Ok(())
+    }
+
+    fn capabilities_string(&mut self) -> anyhow::Result<String> {
+        let caps = self.display.handle.capabilities_string()?;
+        Ok(String::from_utf8_lossy(&caps).into_owned())
+    }
+}
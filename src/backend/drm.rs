@@ -0,0 +1,56 @@
+use std::{fs, path::Path};
+
+/// Root of the DRM connectors in sysfs, e.g. `/sys/class/drm/card1-DP-2`
+const DRM_ROOT: &str = "/sys/class/drm";
+
+/// Connected DRM connectors, with their name (e.g. `DP-2`) and sysfs directory
+fn connectors() -> Vec<(String, std::path::PathBuf)> {
+    let Ok(entries) = fs::read_dir(DRM_ROOT) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let dir_name = entry.file_name().to_string_lossy().into_owned();
+            // card1-DP-2 -> DP-2
+            let (card, connector) = dir_name.split_once('-')?;
+            if !card.starts_with("card") {
+                return None;
+            }
+            let path = entry.path();
+            is_connected(&path).then(|| (connector.to_owned(), path))
+        })
+        .collect()
+}
+
+fn is_connected(path: &Path) -> bool {
+    fs::read_to_string(path.join("status")).is_ok_and(|status| status.trim() == "connected")
+}
+
+/// Name of the connector whose monitor sent this EDID
+pub fn connector_for_edid(edid: &[u8]) -> Option<String> {
+    // the base block is enough to identify a monitor, extensions are not always read
+    const BASE_BLOCK: usize = 128;
+    let edid = edid.get(..BASE_BLOCK)?;
+
+    connectors()
+        .into_iter()
+        .find(|(_, path)| {
+            fs::read(path.join("edid"))
+                .is_ok_and(|drm_edid| drm_edid.get(..BASE_BLOCK) == Some(edid))
+        })
+        .map(|(connector, _)| connector)
+}
+
+/// Connector of the internal panel of a laptop
+pub fn internal_connector() -> Option<String> {
+    connectors()
+        .into_iter()
+        .map(|(connector, _)| connector)
+        .find(|connector| {
+            ["eDP", "LVDS", "DSI"]
+                .iter()
+                .any(|prefix| connector.starts_with(prefix))
+        })
+}
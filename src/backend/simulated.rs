@@ -0,0 +1,195 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::bail;
+
+use crate::monitor::DisplayId;
+
+use super::{Backend, DisplayHandle, VcpValue};
+
+/// In-memory monitor, used to run the monitor subscription without DDC hardware.
+///
+/// It can be scripted to fail, lag or report arbitrary values.
+#[derive(Debug, Clone)]
+pub struct SimulatedMonitor {
+    pub id: DisplayId,
+    pub name: String,
+    pub capabilities: String,
+    pub values: HashMap<u8, VcpValue>,
+    /// Number of upcoming reads that will fail
+    pub failing_reads: u32,
+    /// Number of upcoming writes that will fail
+    pub failing_writes: u32,
+    /// Time taken by every DDC call
+    pub latency: Duration,
+    /// Hidden from enumeration, like an unplugged monitor
+    pub disconnected: bool,
+    /// Accepts writes but never answers reads
+    pub write_only: bool,
+}
+
+impl SimulatedMonitor {
+    pub fn new(id: impl Into<DisplayId>, name: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            name: name.into(),
+            capabilities: String::from("(prot(monitor)type(lcd)vcp(10 12)mccs_ver(2.1))"),
+            values: HashMap::from([(
+                0x10,
+                VcpValue {
+                    value: 50,
+                    maximum: 100,
+                },
+            )]),
+            failing_reads: 0,
+            failing_writes: 0,
+            latency: Duration::ZERO,
+            disconnected: false,
+            write_only: false,
+        }
+    }
+}
+
+/// Builders used to script the monitors in tests
+#[cfg(test)]
+impl SimulatedMonitor {
+    pub fn with_value(mut self, code: u8, value: u16, maximum: u16) -> Self {
+        self.values.insert(code, VcpValue { value, maximum });
+        self
+    }
+
+    pub fn with_failing_reads(mut self, count: u32) -> Self {
+        self.failing_reads = count;
+        self
+    }
+
+    pub fn with_failing_writes(mut self, count: u32) -> Self {
+        self.failing_writes = count;
+        self
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_disconnected(mut self, disconnected: bool) -> Self {
+        self.disconnected = disconnected;
+        self
+    }
+
+    pub fn with_write_only(mut self, write_only: bool) -> Self {
+        self.write_only = write_only;
+        self
+    }
+}
+
+/// Backend over a set of [`SimulatedMonitor`].
+///
+/// Monitors are shared with the handles returned by [`Backend::enumerate`],
+/// so they can be modified while the subscription is running.
+#[derive(Debug, Clone, Default)]
+pub struct SimulatedBackend {
+    monitors: Vec<Arc<Mutex<SimulatedMonitor>>>,
+}
+
+impl SimulatedBackend {
+    pub fn new(monitors: Vec<SimulatedMonitor>) -> Self {
+        Self {
+            monitors: monitors
+                .into_iter()
+                .map(|m| Arc::new(Mutex::new(m)))
+                .collect(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn monitor(&self, id: &str) -> Option<Arc<Mutex<SimulatedMonitor>>> {
+        self.monitors
+            .iter()
+            .find(|m| m.lock().unwrap().id == id)
+            .cloned()
+    }
+}
+
+impl Backend for SimulatedBackend {
+    fn enumerate(&mut self) -> Vec<Box<dyn DisplayHandle>> {
+        self.monitors
+            .iter()
+            .filter(|m| !m.lock().unwrap().disconnected)
+            .map(|m| Box::new(SimulatedHandle(Arc::clone(m))) as Box<dyn DisplayHandle>)
+            .collect()
+    }
+}
+
+struct SimulatedHandle(Arc<Mutex<SimulatedMonitor>>);
+
+impl SimulatedHandle {
+    fn lock(&self) -> std::sync::MutexGuard<'_, SimulatedMonitor> {
+        let latency = self.0.lock().unwrap().latency;
+        if !latency.is_zero() {
+            std::thread::sleep(latency);
+        }
+        self.0.lock().unwrap()
+    }
+}
+
+impl DisplayHandle for SimulatedHandle {
+    fn id(&self) -> DisplayId {
+        self.0.lock().unwrap().id.clone()
+    }
+
+    fn name(&self) -> String {
+        self.0.lock().unwrap().name.clone()
+    }
+
+    fn get_vcp_feature(&mut self, code: u8) -> anyhow::Result<VcpValue> {
+        let mut monitor = self.lock();
+
+        if monitor.disconnected || monitor.write_only {
+            bail!("no DDC/CI reply");
+        }
+        if monitor.failing_reads > 0 {
+            monitor.failing_reads -= 1;
+            bail!("DDC/CI error: Expected DDC/CI length bit");
+        }
+
+        match monitor.values.get(&code) {
+            Some(value) => Ok(*value),
+            None => bail!("unsupported VCP code {code:#04x}"),
+        }
+    }
+
+    fn set_vcp_feature(&mut self, code: u8, value: u16) -> anyhow::Result<()> {
+        let mut monitor = self.lock();
+
+        if monitor.disconnected {
+            bail!("no DDC/CI reply");
+        }
+        if monitor.failing_writes > 0 {
+            monitor.failing_writes -= 1;
+            bail!("DDC/CI error: write failed");
+        }
+
+        match monitor.values.get_mut(&code) {
+            Some(current) => {
+                current.value = value.min(current.maximum);
+                Ok(())
+            }
+            None => bail!("unsupported VCP code {code:#04x}"),
+        }
+    }
+
+    fn capabilities_string(&mut self) -> anyhow::Result<String> {
+        let monitor = self.lock();
+
+        if monitor.disconnected {
+            bail!("no DDC/CI reply");
+        }
+
+        Ok(monitor.capabilities.clone())
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+
+/// VCP features advertised in a MCCS capabilities string,
+/// e.g. `(prot(monitor)type(lcd)vcp(02 10 12 60(0F 11 12) D6(01 04))mccs_ver(2.1))`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    /// Supported VCP codes, with their allowed values.
+    /// The list of values is empty for continuous features.
+    pub features: BTreeMap<u8, Vec<u16>>,
+}
+
+impl Capabilities {
+    pub fn parse(caps: &str) -> Self {
+        let mut features = BTreeMap::new();
+
+        let Some(vcp) = find_section(caps, "vcp") else {
+            return Self { features };
+        };
+
+        let mut depth = 0;
+        let mut last_code = None;
+
+        for token in vcp
+            .replace('(', " ( ")
+            .replace(')', " ) ")
+            .split_whitespace()
+        {
+            match token {
+                "(" => depth += 1,
+                ")" if depth == 0 => break,
+                ")" => depth -= 1,
+                hex => {
+                    let Ok(value) = u16::from_str_radix(hex, 16) else {
+                        continue;
+                    };
+                    match (depth, last_code) {
+                        (0, _) => {
+                            last_code = u8::try_from(value).ok();
+                            if let Some(code) = last_code {
+                                features.entry(code).or_insert_with(Vec::new);
+                            }
+                        }
+                        (1, Some(code)) => {
+                            features.entry(code).or_insert_with(Vec::new).push(value);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Self { features }
+    }
+
+    pub fn supports(&self, code: u8) -> bool {
+        self.features.contains_key(&code)
+    }
+
+    /// Allowed values of a non-continuous feature
+    pub fn values(&self, code: u8) -> &[u16] {
+        self.features.get(&code).map(Vec::as_slice).unwrap_or(&[])
+    }
+}
+
+/// Whether a feature can be used.
+/// Without capabilities, we assume it is supported and let the monitor answer.
+pub fn supports(capabilities: Option<&Capabilities>, code: u8) -> bool {
+    capabilities.is_none_or(|caps| caps.supports(code))
+}
+
+/// Content of a top level `name(...)` section, starting after the opening parenthesis
+fn find_section<'a>(caps: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!("{name}(");
+
+    caps.match_indices(&pattern)
+        .find(|(i, _)| {
+            !caps[..*i]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+        .map(|(i, _)| &caps[i + pattern.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_values() {
+        let caps = Capabilities::parse("(vcp(02 10 12 60(0F 11 12) D6(01 04))mccs_ver(2.1))");
+
+        assert_eq!(
+            caps.features.keys().copied().collect::<Vec<_>>(),
+            [0x02, 0x10, 0x12, 0x60, 0xD6]
+        );
+        assert!(caps.values(0x10).is_empty());
+        assert_eq!(caps.values(0x60), [0x0F, 0x11, 0x12]);
+        assert_eq!(caps.values(0xD6), [0x01, 0x04]);
+    }
+
+    #[test]
+    fn values_after_a_list() {
+        let caps = Capabilities::parse("vcp(60(0F 11)10)");
+
+        assert_eq!(caps.values(0x60), [0x0F, 0x11]);
+        assert!(caps.supports(0x10));
+        assert!(caps.values(0x10).is_empty());
+    }
+
+    #[test]
+    fn missing_vcp_section() {
+        let caps = Capabilities::parse("(prot(monitor)type(lcd)mccs_ver(2.1))");
+
+        assert!(caps.features.is_empty());
+        assert!(!caps.supports(0x10));
+        // without capabilities at all, everything is tried
+        assert!(supports(None, 0x10));
+        assert!(!supports(Some(&caps), 0x10));
+    }
+
+    #[test]
+    fn section_name_must_match_exactly() {
+        let caps = Capabilities::parse("(xvcp(10)vcpname(12(Contrast))vcp(60(11)))");
+
+        assert_eq!(caps.features.keys().copied().collect::<Vec<_>>(), [0x60]);
+    }
+
+    #[test]
+    fn unterminated_section() {
+        let caps = Capabilities::parse("(vcp(10 12 60(0F 11");
+
+        assert!(caps.supports(0x10));
+        assert!(caps.supports(0x12));
+        assert_eq!(caps.values(0x60), [0x0F, 0x11]);
+    }
+
+    #[test]
+    fn dell_u2415() {
+        let caps = Capabilities::parse(
+            "(prot(monitor)type(LCD)model(U2415)cmds(01 02 03 07 0C E3 F3)\
+             vcp(02 04 05 08 10 12 14(01 04 05 06 08 09 0B 0C) 16 18 1A 52 60(01 0F 11) \
+             AA(01 02) AC AE B2 B6 C6 C8 C9 D6(01 04 05) DC(00 02 03 05) DF E0 E1 \
+             E2(00 01 02 04 0E 12 14 19 1D) F0(05 08 0C) F1 F2 FD)\
+             mswhql(1)asset_eep(40)mccs_ver(2.1))",
+        );
+
+        assert!(caps.supports(0x10));
+        assert!(caps.supports(0x12));
+        assert_eq!(caps.values(0x60), [0x01, 0x0F, 0x11]);
+        assert_eq!(caps.values(0xD6), [0x01, 0x04, 0x05]);
+        // the codes of `cmds` aren't features
+        assert!(!caps.supports(0xE3));
+        assert!(!caps.supports(0x01));
+    }
+
+    #[test]
+    fn lowercase_without_outer_parentheses() {
+        // some monitors omit the outer parentheses and use lowercase hex
+        let caps = Capabilities::parse(
+            "prot(monitor)type(lcd)model(27gl850)cmds(01 02 03 0c e3 f3)\
+             vcp(02 04 05 08 10 12 14(05 08 0b) 16 18 1a 52 60(11 12 0f 10) ac ae b2 b6 c0 c6 \
+             c8 c9 d6(01 04) df 62 8d f4 f5(00 01 02) f6(00 01 02) 4d 4e 4f \
+             15(01 06 11 13 14 28 29 32 48) f7(00 01 02 03) f8(00 01) f9 e4 e5 e6 e7 e8 e9 \
+             ea eb ef fd(00 01) fe(00 01 02) ff)mccs_ver(2.1)mswhql(1)",
+        );
+
+        assert!(caps.supports(0x10));
+        assert!(!caps.supports(0x8e));
+        assert_eq!(caps.values(0x60), [0x11, 0x12, 0x0F, 0x10]);
+        assert!(caps.values(0xFF).is_empty());
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
 use cosmic::{
     cosmic_config::{self, CosmicConfigEntry, cosmic_config_derive::CosmicConfigEntry},
@@ -8,32 +8,177 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     app::{APPID, AppMsg},
+    curve::Curve,
     monitor::DisplayId,
+    schedule::{ScheduleEntry, SunSchedule},
+    worker::WorkerSettings,
 };
 
 pub const CONFIG_VERSION: u64 = 1;
 
+/// Factor applied to the poll interval while the popup is closed
+const POLL_BACKOFF: u32 = 4;
+
 #[derive(Clone, CosmicConfigEntry, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(default)]
 #[derive(Default)]
 pub struct Config {
     pub monitors: HashMap<DisplayId, MonitorConfig>,
+    /// Interval in seconds between background reads of the monitors,
+    /// to follow changes made with their buttons. Disabled if None.
+    pub poll_interval: Option<u64>,
+    /// Read the brightness back after each write, and show the value the monitor reports
+    pub verify_writes: bool,
+    /// Duration in milliseconds of the fade to a new brightness. Instant if None.
+    pub transition_ms: Option<u64>,
+    /// Groups of monitors whose brightness moves together
+    pub link_groups: Vec<LinkGroup>,
+    /// Brightness of all the monitors at times of the day, interpolated in between
+    pub schedule: Vec<ScheduleEntry>,
+    /// Brightness of all the monitors following the sun. Replaces `schedule` when set.
+    pub sun_schedule: Option<SunSchedule>,
+    /// Set the brightness of all the monitors from the ambient light sensor.
+    /// Replaces the schedules when enabled.
+    pub ambient_light: bool,
+    /// Illuminance in lux to position of the sliders, between 0 and 1
+    pub ambient_curve: Option<Curve>,
+    /// Apply the changes of the internal panel brightness to the external monitors.
+    /// Ignored while `ambient_light` is enabled.
+    pub follow_internal: bool,
 }
 
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
 pub struct MonitorConfig {
     pub gamma_map: f32,
+    /// Last brightness set, for write-only monitors that can't report it
+    pub last_brightness: Option<u16>,
+    /// Part of the hardware range reached by the slider at 0%, between 0 and 1
+    pub floor: f32,
+    /// Part of the hardware range reached by the slider at 100%, between 0 and 1
+    pub ceiling: f32,
+    /// Slider to part of the hardware range, both between 0 and 1.
+    /// Replaces the gamma and the range when set.
+    pub curve: Option<Curve>,
 }
 
 impl MonitorConfig {
     pub fn new() -> Self {
-        Self { gamma_map: 1. }
+        Self {
+            gamma_map: 1.,
+            last_brightness: None,
+            floor: 0.,
+            ceiling: 1.,
+            curve: None,
+        }
+    }
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Monitors moving together.
+///
+/// The slider of each member follows a level shared by the group:
+/// `slider = level + offset`
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LinkGroup {
+    pub members: HashMap<DisplayId, Link>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Link {
+    pub offset: f32,
+}
+
+impl Link {
+    fn slider(&self, level: f32) -> f32 {
+        (level + self.offset).clamp(0., 1.)
+    }
+
+    pub fn level(&self, slider: f32) -> f32 {
+        slider - self.offset
+    }
+}
+
+impl LinkGroup {
+    /// Sliders of the other members, when the slider of one of them is moved
+    pub fn follow(&self, id: &str, slider: f32) -> Vec<(DisplayId, f32)> {
+        let Some(link) = self.members.get(id) else {
+            return Vec::new();
+        };
+        let level = link.level(slider);
+
+        self.members
+            .iter()
+            .filter(|(member, _)| *member != id)
+            .map(|(member, link)| (member.clone(), link.slider(level)))
+            .collect()
     }
 }
 
 impl Config {
-    pub fn get_gamma_map(&self, id: &str) -> f32 {
-        self.monitors.get(id).map(|m| m.gamma_map).unwrap_or(1.)
+    pub fn get_monitor_config(&self, id: &str) -> MonitorConfig {
+        self.monitors.get(id).cloned().unwrap_or_default()
+    }
+
+    pub fn has_schedule(&self) -> bool {
+        !self.ambient_light && (self.sun_schedule.is_some() || !self.schedule.is_empty())
+    }
+
+    pub fn follows_internal(&self) -> bool {
+        self.follow_internal && !self.ambient_light
+    }
+
+    pub fn ambient_curve(&self) -> Curve {
+        self.ambient_curve.clone().unwrap_or_else(|| {
+            Curve::from(vec![
+                (0., 0.1),
+                (50., 0.3),
+                (300., 0.6),
+                (1000., 0.9),
+                (10000., 1.),
+            ])
+        })
+    }
+
+    pub fn link_group(&self, id: &str) -> Option<&LinkGroup> {
+        self.link_groups.get(self.link_group_index(id)?)
+    }
+
+    pub fn link_group_index(&self, id: &str) -> Option<usize> {
+        self.link_groups
+            .iter()
+            .position(|group| group.members.contains_key(id))
+    }
+
+    pub fn get_last_brightness(&self, id: &str) -> Option<u16> {
+        self.monitors.get(id).and_then(|m| m.last_brightness)
+    }
+
+    pub fn worker_settings(&self) -> WorkerSettings {
+        WorkerSettings {
+            verify_writes: self.verify_writes,
+            transition: self
+                .transition_ms
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis),
+        }
+    }
+
+    /// Polling is slowed down while the popup is closed, only the panel icon needs to be updated
+    pub fn get_poll_interval(&self, popup_opened: bool) -> Option<Duration> {
+        let secs = self.poll_interval.filter(|secs| *secs > 0)?;
+        let interval = Duration::from_secs(secs);
+
+        Some(if popup_opened {
+            interval
+        } else {
+            interval * POLL_BACKOFF
+        })
     }
 }
 
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+
+/// Piecewise linear function, defined by control points sorted by x
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(from = "Vec<(f32, f32)>", into = "Vec<(f32, f32)>")]
+pub struct Curve {
+    points: Vec<(f32, f32)>,
+}
+
+impl From<Vec<(f32, f32)>> for Curve {
+    fn from(mut points: Vec<(f32, f32)>) -> Self {
+        points.retain(|(x, y)| x.is_finite() && y.is_finite());
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { points }
+    }
+}
+
+impl From<Curve> for Vec<(f32, f32)> {
+    fn from(curve: Curve) -> Self {
+        curve.points
+    }
+}
+
+impl Curve {
+    /// Sample a function at the given x values
+    pub fn sample(xs: &[f32], f: impl Fn(f32) -> f32) -> Self {
+        Self::from(xs.iter().map(|&x| (x, f(x))).collect::<Vec<_>>())
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    /// Move a point vertically. It stays between its neighbours, so the curve can be inverted.
+    pub fn set_y(&mut self, index: usize, y: f32) {
+        let min = index
+            .checked_sub(1)
+            .and_then(|i| self.points.get(i))
+            .map_or(f32::MIN, |p| p.1);
+        let max = self.points.get(index + 1).map_or(f32::MAX, |p| p.1);
+
+        if let Some(point) = self.points.get_mut(index) {
+            point.1 = y.clamp(min, max.max(min));
+        }
+    }
+
+    /// Identity if there is no point
+    pub fn eval(&self, x: f32) -> f32 {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return x;
+        };
+
+        if x <= first.0 {
+            return first.1;
+        }
+        if x >= last.0 {
+            return last.1;
+        }
+
+        self.points
+            .windows(2)
+            .find(|w| x <= w[1].0)
+            .map(|w| lerp(w[0], w[1], x))
+            .unwrap_or(last.1)
+    }
+
+    /// x of the first segment reaching y, the curve is expected to be increasing
+    pub fn inverse(&self, y: f32) -> f32 {
+        let (Some(first), Some(last)) = (self.points.first(), self.points.last()) else {
+            return y;
+        };
+
+        if y <= first.1 {
+            return first.0;
+        }
+
+        self.points
+            .windows(2)
+            .find(|w| y <= w[1].1)
+            .map(|w| lerp((w[0].1, w[0].0), (w[1].1, w[1].0), y))
+            .unwrap_or(last.0)
+    }
+}
+
+/// Linear interpolation between two points, at x
+fn lerp(a: (f32, f32), b: (f32, f32), x: f32) -> f32 {
+    if b.0 == a.0 {
+        return a.1;
+    }
+    a.1 + (x - a.0) * (b.1 - a.1) / (b.0 - a.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn empty_is_identity() {
+        let curve = Curve::default();
+
+        assert_eq!(curve.eval(0.3), 0.3);
+        assert_eq!(curve.inverse(0.3), 0.3);
+    }
+
+    #[test]
+    fn eval_clamps_out_of_range() {
+        let curve = Curve::from(vec![(0.2, 0.1), (0.8, 0.9)]);
+
+        assert_eq!(curve.eval(-1.0), 0.1);
+        assert_eq!(curve.eval(0.0), 0.1);
+        assert_eq!(curve.eval(1.0), 0.9);
+        assert_eq!(curve.eval(2.0), 0.9);
+        assert_close(curve.eval(0.5), 0.5);
+    }
+
+    #[test]
+    fn eval_between_points() {
+        let curve = Curve::from(vec![(0.0, 0.0), (0.5, 0.2), (1.0, 1.0)]);
+
+        assert_close(curve.eval(0.25), 0.1);
+        assert_close(curve.eval(0.5), 0.2);
+        assert_close(curve.eval(0.75), 0.6);
+    }
+
+    #[test]
+    fn flat_segment() {
+        let curve = Curve::from(vec![(0.0, 0.0), (0.4, 0.5), (0.6, 0.5), (1.0, 1.0)]);
+
+        assert_close(curve.eval(0.5), 0.5);
+        // the first x reaching the value
+        assert_close(curve.inverse(0.5), 0.4);
+        assert_close(curve.inverse(0.75), 0.8);
+    }
+
+    #[test]
+    fn vertical_segment() {
+        let curve = Curve::from(vec![(0.0, 0.0), (0.5, 0.2), (0.5, 0.8), (1.0, 1.0)]);
+
+        assert_close(curve.eval(0.5), 0.2);
+        assert_close(curve.inverse(0.5), 0.5);
+    }
+
+    #[test]
+    fn inverse_clamps_out_of_range() {
+        let curve = Curve::from(vec![(0.0, 0.2), (1.0, 0.8)]);
+
+        assert_eq!(curve.inverse(0.0), 0.0);
+        assert_eq!(curve.inverse(1.0), 1.0);
+        assert_close(curve.inverse(0.5), 0.5);
+    }
+
+    #[test]
+    fn inverse_of_eval() {
+        let curve = Curve::from(vec![(0.0, 0.05), (0.25, 0.1), (0.5, 0.3), (1.0, 1.0)]);
+
+        for i in 0..=20 {
+            let x = i as f32 / 20.0;
+            assert_close(curve.inverse(curve.eval(x)), x);
+        }
+    }
+
+    #[test]
+    fn set_y_stays_between_neighbours() {
+        let mut curve = Curve::from(vec![(0.0, 0.0), (0.5, 0.5), (1.0, 1.0)]);
+
+        curve.set_y(1, 2.0);
+        assert_eq!(curve.points()[1], (0.5, 1.0));
+
+        curve.set_y(1, -1.0);
+        assert_eq!(curve.points()[1], (0.5, 0.0));
+
+        // the ends are only bounded by their single neighbour
+        curve.set_y(0, -0.5);
+        assert_eq!(curve.points()[0], (0.0, -0.5));
+        curve.set_y(2, 0.2);
+        assert_eq!(curve.points()[2], (1.0, 0.2));
+
+        // out of range
+        curve.set_y(3, 0.5);
+        assert_eq!(curve.points().len(), 3);
+    }
+
+    #[test]
+    fn deserialize_sorts_and_drops_invalid_points() {
+        let curve: Curve =
+            ron::from_str("[(1.0, 1.0), (0.0, 0.1), (NaN, 0.5), (0.5, 0.4)]").unwrap();
+
+        assert_eq!(curve.points(), [(0.0, 0.1), (0.5, 0.4), (1.0, 1.0)]);
+        assert_eq!(
+            ron::to_string(&curve).unwrap(),
+            "[(0.0,0.1),(0.5,0.4),(1.0,1.0)]"
+        );
+    }
+}
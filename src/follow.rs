@@ -0,0 +1,48 @@
+use std::{path::Path, time::Duration};
+
+use cosmic::iced::{
+    Subscription,
+    futures::{SinkExt, Stream, channel::mpsc::Sender},
+    stream,
+};
+
+use crate::app::AppMsg;
+use crate::backend::{backlight_level, backlight_root, find_backlight};
+
+/// The brightness keys change the backlight without any event, so it is read often
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Changes of the brightness of the internal panel
+pub fn sub() -> Subscription<AppMsg> {
+    Subscription::run(internal_brightness)
+}
+
+fn internal_brightness() -> impl Stream<Item = AppMsg> {
+    stream::channel(10, |mut output: Sender<AppMsg>| async move {
+        let root = backlight_root();
+
+        let Some(path) = find_backlight(Path::new(&root)) else {
+            warn!("no internal panel backlight in {root}");
+            return;
+        };
+
+        let mut last = None;
+
+        loop {
+            match backlight_level(&path) {
+                Ok(level) => {
+                    if let Some(previous) = last.filter(|previous| *previous != level) {
+                        let msg = AppMsg::InternalBrightnessChanged(previous, level);
+                        if output.send(msg).await.is_err() {
+                            return;
+                        }
+                    }
+                    last = Some(level);
+                }
+                Err(e) => warn!("can't read the internal panel backlight: {e}"),
+            }
+
+            tokio::time::sleep(FOLLOW_INTERVAL).await;
+        }
+    })
+}
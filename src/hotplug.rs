@@ -0,0 +1,46 @@
+use tokio::{
+    io::unix::AsyncFd,
+    sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+};
+
+/// Receive a message when displays may have been connected or disconnected.
+///
+/// Any sender can drive it, the monitor subscription doesn't know about udev.
+pub type HotplugReceiver = UnboundedReceiver<()>;
+
+/// Watch udev for DRM and i2c devices being added or removed
+pub fn udev_events() -> HotplugReceiver {
+    let (tx, rx) = unbounded_channel();
+
+    tokio::spawn(async move {
+        if let Err(e) = watch_udev(tx).await {
+            error!("can't watch udev events: {e}");
+        }
+    });
+
+    rx
+}
+
+async fn watch_udev(tx: UnboundedSender<()>) -> std::io::Result<()> {
+    let socket = udev::MonitorBuilder::new()?
+        .match_subsystem("drm")?
+        .match_subsystem("i2c-dev")?
+        .listen()?;
+
+    let socket = AsyncFd::new(socket)?;
+
+    loop {
+        let mut guard = socket.readable().await?;
+
+        for event in guard.get_inner().iter() {
+            debug!("udev event: {} {:?}", event.event_type(), event.syspath());
+
+            if tx.send(()).is_err() {
+                // the monitor subscription is gone
+                return Ok(());
+            }
+        }
+
+        guard.clear_ready();
+    }
+}
@@ -7,12 +7,23 @@ use crate::localize::localize;
 #[macro_use]
 extern crate tracing;
 
+mod ambient;
 mod app;
+mod backend;
+mod capabilities;
 mod config;
+mod curve;
+mod follow;
+mod hotplug;
 mod icon;
 mod localize;
 mod monitor;
+mod output;
+mod schedule;
+#[cfg(test)]
+mod test_util;
 mod view;
+mod worker;
 
 fn setup_logs() {
     use tracing_subscriber::{EnvFilter, fmt, layer::SubscriberExt, util::SubscriberInitExt};
@@ -1,19 +1,33 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Arc, Mutex},
     time::Duration,
 };
 
 use cosmic::iced::{
-    futures::{SinkExt, Stream},
+    futures::{SinkExt, Stream, channel::mpsc::Sender},
     stream,
 };
-use ddc_hi::{Ddc, Display};
-use tokio::sync::watch::Receiver;
+use tokio::{
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+        watch,
+    },
+    time::Instant,
+};
 
 use crate::app::AppMsg;
+use crate::backend::{self, Backend, DisplayHandle, VcpValue};
+use crate::capabilities::{Capabilities, supports};
+use crate::hotplug::{self, HotplugReceiver};
+use crate::worker::{Command, Worker, WorkerSettings};
+
+pub const BRIGHTNESS_CODE: u8 = 0x10;
+pub const CONTRAST_CODE: u8 = 0x12;
+pub const INPUT_SOURCE_CODE: u8 = 0x60;
 
-const BRIGHTNESS_CODE: u8 = 0x10;
+/// Time to wait after a hotplug event before enumerating displays
+const HOTPLUG_DELAY: Duration = Duration::from_secs(2);
 
 pub type DisplayId = String;
 pub type ScreenBrightness = u16;
@@ -22,141 +36,929 @@ pub type ScreenBrightness = u16;
 pub struct MonitorInfo {
     pub name: String,
     pub brightness: u16,
+    /// Maximum brightness value reported by the monitor
+    pub max_brightness: u16,
+    /// None if the monitor doesn't report its contrast
+    pub contrast: Option<u16>,
+    /// Maximum contrast value reported by the monitor
+    pub max_contrast: u16,
+    /// None if the monitor doesn't report its input source
+    pub input_source: Option<u16>,
+    /// None if the capabilities string couldn't be fetched
+    pub capabilities: Option<Capabilities>,
+    pub status: MonitorStatus,
+    /// Id used by previous versions, to migrate the config
+    pub legacy_id: Option<DisplayId>,
+    /// Name of the DRM connector, e.g. `DP-2`, also used by the Wayland output
+    pub connector: Option<String>,
+}
+
+impl MonitorInfo {
+    /// Placeholder for a display that can't be controlled
+    pub fn unavailable(
+        display: &dyn DisplayHandle,
+        capabilities: Option<Capabilities>,
+        status: MonitorStatus,
+    ) -> Self {
+        Self {
+            name: display.name(),
+            brightness: 0,
+            max_brightness: 100,
+            contrast: None,
+            max_contrast: 100,
+            input_source: None,
+            capabilities,
+            status,
+            legacy_id: display.legacy_id(),
+            connector: display.connector(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MonitorStatus {
+    Available,
+    /// No write access to `/dev/i2c-*`
+    PermissionDenied,
+    /// The monitor doesn't answer, DDC/CI may be disabled in its menu
+    NoReply,
+    ChecksumError,
+    /// The monitor doesn't support brightness control
+    Unsupported,
+    /// The monitor accepts writes but doesn't answer reads,
+    /// the brightness shown is the last one set
+    WriteOnly,
+    Error,
+}
+
+impl MonitorStatus {
+    pub fn from_error(err: &anyhow::Error) -> Self {
+        if let Some(err) = err.chain().find_map(|e| e.downcast_ref::<std::io::Error>()) {
+            return match err.kind() {
+                std::io::ErrorKind::PermissionDenied => MonitorStatus::PermissionDenied,
+                _ => MonitorStatus::NoReply,
+            };
+        }
+
+        // errors of the DDC crates are often only known by their message
+        let msg = format!("{err:#}").to_lowercase();
+
+        if msg.contains("permission denied") {
+            MonitorStatus::PermissionDenied
+        } else if msg.contains("checksum") {
+            MonitorStatus::ChecksumError
+        } else if msg.contains("reply")
+            || msg.contains("remote i/o")
+            || msg.contains("timed out")
+            || msg.contains("no such device")
+        {
+            MonitorStatus::NoReply
+        } else {
+            MonitorStatus::Error
+        }
+    }
+
+    /// Whether the monitor failed to answer
+    pub fn is_error(&self) -> bool {
+        !matches!(
+            self,
+            MonitorStatus::Available | MonitorStatus::Unsupported | MonitorStatus::WriteOnly
+        )
+    }
+
+    /// Whether the monitor is worth probing again, a write-only one may answer reads later
+    pub fn needs_probe(&self) -> bool {
+        self.is_error() || *self == MonitorStatus::WriteOnly
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum EventToSub {
     Refresh,
+    /// Read the displays in the background, only report changes
+    Poll,
+    /// Enumerate displays again, to find new ones and forget disconnected ones.
+    /// Known displays are read again.
+    Rescan,
     Set(DisplayId, ScreenBrightness),
+    SetContrast(DisplayId, u16),
+    SetInputSource(DisplayId, u16),
+    /// Probe a display that failed again
+    Retry(DisplayId),
+    Settings(WorkerSettings),
 }
 
 enum State {
     Waiting,
     Fetch,
     Ready(
-        HashMap<DisplayId, Arc<Mutex<Display>>>,
-        Receiver<EventToSub>,
+        HashMap<DisplayId, UnboundedSender<Command>>,
+        UnboundedReceiver<EventToSub>,
     ),
 }
 
 pub fn sub() -> impl Stream<Item = AppMsg> {
-    stream::channel(
-        100,
-        |mut output: cosmic::iced::futures::channel::mpsc::Sender<AppMsg>| async move {
-            let mut state = State::Waiting;
-            let mut failed_attempts = 0;
+    stream::channel(100, |output: Sender<AppMsg>| async move {
+        run(
+            backend::default_backend(),
+            hotplug::udev_events(),
+            HOTPLUG_DELAY,
+            output,
+        )
+        .await
+    })
+}
 
-            let mut duration = Duration::from_millis(50);
+/// Hotplug events are debounced by `hotplug_delay` before enumerating the displays again
+pub async fn run(
+    backend: Box<dyn Backend>,
+    mut hotplug: HotplugReceiver,
+    hotplug_delay: Duration,
+    mut output: Sender<AppMsg>,
+) {
+    let mut state = State::Waiting;
+    let mut failed_attempts = 0;
+    // enumerating and probing block on i2c, they run on the blocking threads
+    let backend = Arc::new(Mutex::new(backend));
+    // fetched once per display, it can take a while
+    let capabilities_cache = Arc::new(Mutex::new(HashMap::new()));
 
-            loop {
-                match &mut state {
-                    State::Waiting => {
-                        tokio::time::sleep(duration).await;
-                        duration *= 2;
-                        state = State::Fetch;
+    let mut duration = Duration::from_millis(50);
+
+    let (settings_tx, settings_rx) = watch::channel(WorkerSettings::default());
+
+    // end of the burst of hotplug events
+    let mut rescan_at: Option<Instant> = None;
+    // the rescans run in the background, their results come back here
+    let (scan_tx, mut scan_rx) = unbounded_channel();
+    let mut scanning = false;
+    let mut rescan_pending = false;
+
+    loop {
+        match &mut state {
+            State::Waiting => {
+                tokio::time::sleep(duration).await;
+                duration *= 2;
+                state = State::Fetch;
+            }
+            State::Fetch => {
+                debug!("start enumerate");
+
+                let scan = scan(&backend, &capabilities_cache, HashSet::new()).await;
+
+                // on my machine, i get this error when starting the session
+                // can't get_vcp_feature: DDC/CI error: Expected DDC/CI length bit
+                // This go away after the third attempt
+                if scan.new.iter().any(|probed| probed.error.is_some()) {
+                    failed_attempts += 1;
+
+                    // On some monitors this error is permanent
+                    // So we mark the app as ready if at least one monitor is loaded after 5 attempts
+                    if failed_attempts < 5 {
+                        state = State::Waiting;
+                        continue;
                     }
-                    State::Fetch => {
-                        let mut res = HashMap::new();
-
-                        let mut displays = HashMap::new();
-
-                        debug!("start enumerate");
-
-                        let mut some_failed = false;
-                        for mut display in Display::enumerate() {
-                            let brightness = match display.handle.get_vcp_feature(BRIGHTNESS_CODE) {
-                                Ok(v) => v.value(),
-                                // on my machine, i get this error when starting the session
-                                // can't get_vcp_feature: DDC/CI error: Expected DDC/CI length bit
-                                // This go away after the third attempt
-                                Err(e) => {
-                                    error!("can't get_vcp_feature: {e}");
-                                    some_failed = true;
-                                    continue;
-                                }
-                            };
-                            debug_assert!(brightness <= 100);
+                }
 
-                            let mon = MonitorInfo {
-                                name: display.info.model_name.clone().unwrap_or_default(),
-                                brightness,
-                            };
+                debug!("end enumerate");
+
+                // The failing monitors are shown with their error, so they can be retried.
+                // Their workers also keep probing them in the background.
+                let mut res = HashMap::new();
+                let mut workers = HashMap::new();
+
+                for Probed { display, info, .. } in scan.new {
+                    let id = display.id();
+                    let tx = Worker::spawn(
+                        id.clone(),
+                        display,
+                        &info,
+                        settings_rx.clone(),
+                        output.clone(),
+                    );
+                    workers.insert(id.clone(), tx);
+                    res.insert(id, info);
+                }
+
+                // Every event is kept, so a change sent to several monitors reaches all of them.
+                // Pending values are coalesced per display by the workers.
+                let (tx, rx) = unbounded_channel();
 
-                            res.insert(display.info.id.clone(), mon);
-                            displays.insert(display.info.id.clone(), Arc::new(Mutex::new(display)));
+                output
+                    .send(AppMsg::SubscriptionReady((res, tx)))
+                    .await
+                    .unwrap();
+                state = State::Ready(workers, rx);
+            }
+            State::Ready(workers, rx) => {
+                let last = tokio::select! {
+                    event = rx.recv() => {
+                        match event {
+                            Some(event) => event,
+                            // the app dropped the sender
+                            None => return,
                         }
+                    }
+                    Some(()) = hotplug.recv() => {
+                        // wait for the end of the burst of events,
+                        // and let the monitor wake up before talking to it
+                        rescan_at = Some(Instant::now() + hotplug_delay);
+                        continue;
+                    }
+                    () = tokio::time::sleep_until(rescan_at.unwrap_or_else(Instant::now)),
+                        if rescan_at.is_some() =>
+                    {
+                        rescan_at = None;
+                        EventToSub::Rescan
+                    }
+                    Some(scan) = scan_rx.recv() => {
+                        scanning = false;
+                        apply_rescan(scan, workers, &settings_rx, &mut output).await;
 
-                        if some_failed {
-                            failed_attempts += 1;
+                        if !std::mem::take(&mut rescan_pending) {
+                            continue;
                         }
+                        EventToSub::Rescan
+                    }
+                };
 
-                        // On some monitors this error is permanent
-                        // So we mark the app as ready if at least one monitor is loaded after 5 attempts
-                        if some_failed && failed_attempts < 5 {
-                            state = State::Waiting;
+                match last {
+                    EventToSub::Refresh => {
+                        for id in workers.keys() {
+                            let command = Command::Read {
+                                only_changes: false,
+                            };
+                            send_command(workers, id, command);
+                        }
+                    }
+                    EventToSub::Poll => {
+                        for id in workers.keys() {
+                            send_command(workers, id, Command::Read { only_changes: true });
+                        }
+                    }
+                    EventToSub::Rescan => {
+                        // the displays may have changed since the running scan started
+                        if scanning {
+                            rescan_pending = true;
                             continue;
                         }
 
-                        debug!("end enumerate");
+                        debug!("start rescan");
+                        scanning = true;
 
-                        let (tx, mut rx) = tokio::sync::watch::channel(EventToSub::Refresh);
-                        rx.mark_unchanged();
+                        // the workers keep receiving commands while it runs
+                        let known = workers.keys().cloned().collect();
+                        let backend = Arc::clone(&backend);
+                        let capabilities_cache = Arc::clone(&capabilities_cache);
+                        let scan_tx = scan_tx.clone();
 
-                        output
-                            .send(AppMsg::SubscriptionReady((res, tx)))
-                            .await
-                            .unwrap();
-                        state = State::Ready(displays, rx);
+                        tokio::spawn(async move {
+                            let scan = scan(&backend, &capabilities_cache, known).await;
+                            let _ = scan_tx.send(scan);
+                        });
                     }
-                    State::Ready(displays, rx) => {
-                        rx.changed().await.unwrap();
-
-                        let last = rx.borrow_and_update().clone();
-                        match last {
-                            EventToSub::Refresh => {
-                                for (id, display) in displays {
-                                    let res = display
-                                        .lock()
-                                        .unwrap()
-                                        .handle
-                                        .get_vcp_feature(BRIGHTNESS_CODE);
-
-                                    match res {
-                                        Ok(value) => {
-                                            output
-                                                .send(AppMsg::BrightnessWasUpdated(
-                                                    id.clone(),
-                                                    value.value(),
-                                                ))
-                                                .await
-                                                .unwrap();
-                                        }
-                                        Err(err) => error!("{:?}", err),
-                                    }
-                                }
-                            }
-                            EventToSub::Set(id, value) => {
-                                debug_assert!(value <= 100);
-                                let display = Arc::clone(displays.get_mut(&id).unwrap());
-
-                                let j = tokio::task::spawn_blocking(move || {
-                                    if let Err(err) = display
-                                        .lock()
-                                        .unwrap()
-                                        .handle
-                                        .set_vcp_feature(BRIGHTNESS_CODE, value)
-                                    {
-                                        error!("{:?}", err);
-                                    }
-                                });
-
-                                j.await.unwrap();
-                                tokio::time::sleep(Duration::from_millis(50)).await;
-                            }
-                        }
+                    EventToSub::Set(id, value) => {
+                        send_command(workers, &id, Command::Set(BRIGHTNESS_CODE, value));
+                    }
+                    EventToSub::SetContrast(id, value) => {
+                        send_command(workers, &id, Command::Set(CONTRAST_CODE, value));
+                    }
+                    EventToSub::SetInputSource(id, value) => {
+                        send_command(workers, &id, Command::Set(INPUT_SOURCE_CODE, value));
+                    }
+                    EventToSub::Retry(id) => {
+                        send_command(workers, &id, Command::Probe);
                     }
+                    EventToSub::Settings(settings) => {
+                        settings_tx.send_replace(settings);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Start workers for the new displays of a rescan, and stop the ones of the displays gone
+async fn apply_rescan(
+    scan: Scan,
+    workers: &mut HashMap<DisplayId, UnboundedSender<Command>>,
+    settings_rx: &watch::Receiver<WorkerSettings>,
+    output: &mut Sender<AppMsg>,
+) {
+    // known displays are only read again, to catch up with
+    // changes made while they were asleep
+    for id in workers.keys().filter(|id| scan.present.contains(*id)) {
+        send_command(workers, id, Command::Read { only_changes: true });
+    }
+
+    for Probed { display, info, .. } in scan.new {
+        let id = display.id();
+        if workers.contains_key(&id) {
+            continue;
+        }
+
+        info!("new display {id}");
+        let tx = Worker::spawn(
+            id.clone(),
+            display,
+            &info,
+            settings_rx.clone(),
+            output.clone(),
+        );
+        workers.insert(id.clone(), tx);
+        output.send(AppMsg::MonitorAdded(id, info)).await.unwrap();
+    }
+
+    let removed = workers
+        .keys()
+        .filter(|id| !scan.present.contains(*id))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    for id in removed {
+        info!("display {id} removed");
+        workers.remove(&id);
+        output.send(AppMsg::MonitorRemoved(id)).await.unwrap();
+    }
+
+    debug!("end rescan");
+}
+
+/// Display found by an enumeration, with its initial state
+struct Probed {
+    display: Box<dyn DisplayHandle>,
+    /// Placeholder with the status of the error if the probe failed
+    info: MonitorInfo,
+    error: Option<anyhow::Error>,
+}
+
+/// Result of an enumeration of the displays
+struct Scan {
+    present: HashSet<DisplayId>,
+    /// Displays that were not known before
+    new: Vec<Probed>,
+}
+
+/// Enumerate the displays and probe the ones not in `known`, on a blocking thread
+async fn scan(
+    backend: &Arc<Mutex<Box<dyn Backend>>>,
+    capabilities_cache: &Arc<Mutex<HashMap<DisplayId, Capabilities>>>,
+    known: HashSet<DisplayId>,
+) -> Scan {
+    let backend = Arc::clone(backend);
+    let capabilities_cache = Arc::clone(capabilities_cache);
+
+    let j = tokio::task::spawn_blocking(move || {
+        let mut backend = backend.lock().unwrap();
+        let mut capabilities_cache = capabilities_cache.lock().unwrap();
+
+        let mut scan = Scan {
+            present: HashSet::new(),
+            new: Vec::new(),
+        };
+
+        for mut display in backend.enumerate() {
+            let id = display.id();
+            scan.present.insert(id.clone());
+
+            if known.contains(&id) {
+                continue;
+            }
+
+            let capabilities = get_capabilities(&mut display, &mut capabilities_cache);
+
+            let (info, error) = match probe(&mut display, capabilities.clone(), None) {
+                Ok(info) => (info, None),
+                Err(e) => {
+                    error!("can't get_vcp_feature: {e}");
+                    let info = MonitorInfo::unavailable(
+                        display.as_ref(),
+                        capabilities,
+                        MonitorStatus::from_error(&e),
+                    );
+                    (info, Some(e))
+                }
+            };
+
+            scan.new.push(Probed {
+                display,
+                info,
+                error,
+            });
+        }
+
+        scan
+    });
+
+    j.await.unwrap()
+}
+
+fn get_capabilities(
+    display: &mut Box<dyn DisplayHandle>,
+    cache: &mut HashMap<DisplayId, Capabilities>,
+) -> Option<Capabilities> {
+    let id = display.id();
+
+    if let Some(caps) = cache.get(&id) {
+        return Some(caps.clone());
+    }
+
+    let caps = fetch_capabilities(display)?;
+    cache.insert(id, caps.clone());
+    Some(caps)
+}
+
+pub fn fetch_capabilities(display: &mut Box<dyn DisplayHandle>) -> Option<Capabilities> {
+    match display.capabilities_string() {
+        Ok(caps) => Some(Capabilities::parse(&caps)),
+        Err(e) => {
+            warn!("can't get capabilities of {}: {e}", display.id());
+            None
+        }
+    }
+}
+
+/// Read the initial state of a display.
+/// Fail if the brightness can't be read, unless the display is write-only.
+///
+/// `known_brightness` is written to test a display that doesn't answer reads.
+pub fn probe(
+    display: &mut Box<dyn DisplayHandle>,
+    capabilities: Option<Capabilities>,
+    known_brightness: Option<u16>,
+) -> anyhow::Result<MonitorInfo> {
+    let id = display.id();
+
+    if !supports(capabilities.as_ref(), BRIGHTNESS_CODE) {
+        warn!("{id} doesn't support brightness");
+
+        return Ok(MonitorInfo::unavailable(
+            display.as_ref(),
+            capabilities,
+            MonitorStatus::Unsupported,
+        ));
+    }
+
+    let brightness = match display.get_vcp_feature(BRIGHTNESS_CODE) {
+        Ok(brightness) => brightness,
+        Err(e) if MonitorStatus::from_error(&e) == MonitorStatus::NoReply => {
+            return match probe_write_only(display, known_brightness) {
+                Ok(info) => {
+                    warn!("{id} doesn't reply to reads, use it as write-only: {e}");
+                    Ok(info)
+                }
+                Err(write_err) => {
+                    debug!("{id} isn't write-only either: {write_err}");
+                    Err(e)
+                }
+            };
+        }
+        Err(e) => return Err(e),
+    };
+
+    let contrast = if supports(capabilities.as_ref(), CONTRAST_CODE) {
+        match display.get_vcp_feature(CONTRAST_CODE) {
+            Ok(v) => Some(v),
+            Err(e) => {
+                warn!("can't get contrast of {id}: {e}");
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let input_source = capabilities
+        .as_ref()
+        .filter(|caps| caps.supports(INPUT_SOURCE_CODE))
+        .and_then(|_| display.get_vcp_feature(INPUT_SOURCE_CODE).ok())
+        .map(|v| input_source_value(v.value));
+
+    Ok(MonitorInfo {
+        name: display.name(),
+        brightness: brightness.value.min(maximum(&brightness)),
+        max_brightness: maximum(&brightness),
+        contrast: contrast.map(|c| c.value.min(maximum(&c))),
+        max_contrast: contrast.as_ref().map(maximum).unwrap_or(100),
+        input_source,
+        capabilities,
+        status: MonitorStatus::Available,
+        legacy_id: display.legacy_id(),
+        connector: display.connector(),
+    })
+}
+
+/// The display listens but refuses reads, if it answers the capabilities request
+/// and accepts a write right now. The cached capabilities may be from before it was turned off.
+fn probe_write_only(
+    display: &mut Box<dyn DisplayHandle>,
+    known_brightness: Option<u16>,
+) -> anyhow::Result<MonitorInfo> {
+    let capabilities = Capabilities::parse(&display.capabilities_string()?);
+
+    let mut info = MonitorInfo::unavailable(
+        display.as_ref(),
+        Some(capabilities),
+        MonitorStatus::WriteOnly,
+    );
+    // unknown until set, the middle of the range like the slider
+    info.brightness = known_brightness.unwrap_or(info.max_brightness / 2);
+    display.set_vcp_feature(BRIGHTNESS_CODE, info.brightness)?;
+
+    Ok(info)
+}
+
+fn send_command(
+    workers: &HashMap<DisplayId, UnboundedSender<Command>>,
+    id: &str,
+    command: Command,
+) {
+    match workers.get(id) {
+        Some(tx) => {
+            if tx.send(command).is_err() {
+                error!("worker of {id} is gone");
+            }
+        }
+        None => warn!("unknown display {id}"),
+    }
+}
+
+/// Maximum value of a continuous feature.
+/// Some monitors report a maximum of 0, assume the usual 100 in this case.
+fn maximum(value: &VcpValue) -> u16 {
+    if value.maximum == 0 {
+        100
+    } else {
+        value.maximum
+    }
+}
+
+/// Some monitors put garbage in the high byte of the input source
+pub fn input_source_value(value: u16) -> u16 {
+    value & 0xFF
+}
+
+/// Name of an input source, as defined by the MCCS standard
+pub fn input_source_name(value: u16) -> String {
+    match value {
+        0x01 => "VGA 1".into(),
+        0x02 => "VGA 2".into(),
+        0x03 => "DVI 1".into(),
+        0x04 => "DVI 2".into(),
+        0x05 => "Composite 1".into(),
+        0x06 => "Composite 2".into(),
+        0x07 => "S-Video 1".into(),
+        0x08 => "S-Video 2".into(),
+        0x09 => "Tuner 1".into(),
+        0x0A => "Tuner 2".into(),
+        0x0B => "Tuner 3".into(),
+        0x0C => "Component 1".into(),
+        0x0D => "Component 2".into(),
+        0x0E => "Component 3".into(),
+        0x0F => "DisplayPort 1".into(),
+        0x10 => "DisplayPort 2".into(),
+        0x11 => "HDMI 1".into(),
+        0x12 => "HDMI 2".into(),
+        0x1B => "USB-C".into(),
+        v => format!("{v:#04x}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use cosmic::iced::futures::{
+        StreamExt,
+        channel::mpsc::{Receiver, channel},
+    };
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+    use crate::backend::{SimulatedBackend, SimulatedMonitor};
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+    const HOTPLUG_DELAY: Duration = Duration::from_millis(10);
+
+    struct Harness {
+        backend: SimulatedBackend,
+        messages: Receiver<AppMsg>,
+        hotplug: UnboundedSender<()>,
+    }
+
+    impl Harness {
+        fn start(monitors: Vec<SimulatedMonitor>) -> Self {
+            let backend = SimulatedBackend::new(monitors);
+            let (hotplug_tx, hotplug_rx) = unbounded_channel();
+            let (output, messages) = channel(100);
+
+            tokio::spawn(run(
+                Box::new(backend.clone()),
+                hotplug_rx,
+                HOTPLUG_DELAY,
+                output,
+            ));
+
+            Self {
+                backend,
+                messages,
+                hotplug: hotplug_tx,
+            }
+        }
+
+        async fn next(&mut self) -> AppMsg {
+            tokio::time::timeout(TIMEOUT, self.messages.next())
+                .await
+                .expect("no message from the subscription")
+                .expect("the subscription stopped")
+        }
+
+        async fn ready(
+            &mut self,
+        ) -> (HashMap<DisplayId, MonitorInfo>, UnboundedSender<EventToSub>) {
+            loop {
+                if let AppMsg::SubscriptionReady(ready) = self.next().await {
+                    return ready;
+                }
+            }
+        }
+
+        fn set_disconnected(&self, id: &str, disconnected: bool) {
+            let monitor = self.backend.monitor(id).unwrap();
+            monitor.lock().unwrap().disconnected = disconnected;
+        }
+
+        fn value(&self, id: &str, code: u8) -> Option<u16> {
+            let monitor = self.backend.monitor(id)?;
+            let monitor = monitor.lock().unwrap();
+            monitor.values.get(&code).map(|v| v.value)
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_until_the_monitor_answers() {
+        let mut harness =
+            Harness::start(vec![SimulatedMonitor::new("a", "A").with_failing_reads(2)]);
+
+        let (monitors, _tx) = harness.ready().await;
+
+        assert_eq!(monitors["a"].status, MonitorStatus::Available);
+        assert_eq!(monitors["a"].brightness, 50);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_five_attempts() {
+        let mut harness = Harness::start(vec![
+            SimulatedMonitor::new("a", "A").with_failing_reads(u32::MAX),
+            SimulatedMonitor::new("b", "B"),
+        ]);
+
+        let (monitors, _tx) = harness.ready().await;
+
+        // 5 probes during the enumeration, then the worker probes it again in the background
+        let remaining = harness
+            .backend
+            .monitor("a")
+            .unwrap()
+            .lock()
+            .unwrap()
+            .failing_reads;
+        assert!(u32::MAX - remaining >= 5);
+        assert!(monitors["a"].status.is_error());
+        assert_eq!(monitors["b"].status, MonitorStatus::Available);
+    }
+
+    #[tokio::test]
+    async fn polling_waits_for_the_next_probe() {
+        let mut harness = Harness::start(vec![
+            SimulatedMonitor::new("a", "A").with_failing_reads(u32::MAX),
+            SimulatedMonitor::new("b", "B"),
+        ]);
+
+        let (_, tx) = harness.ready().await;
+
+        let failing_reads = || {
+            let monitor = harness.backend.monitor("a").unwrap();
+            monitor.lock().unwrap().failing_reads
+        };
+        let remaining = failing_reads();
+
+        for _ in 0..5 {
+            tx.send(EventToSub::Refresh).unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        assert_eq!(failing_reads(), remaining);
+
+        // asked by the user
+        tx.send(EventToSub::Retry("a".into())).unwrap();
+
+        tokio::time::timeout(TIMEOUT, async {
+            while failing_reads() == remaining {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the display wasn't probed");
+    }
+
+    #[tokio::test]
+    async fn set_reaches_the_monitor() {
+        let mut harness = Harness::start(vec![
+            SimulatedMonitor::new("a", "A").with_latency(Duration::from_millis(10)),
+            SimulatedMonitor::new("b", "B"),
+        ]);
+
+        let (_, tx) = harness.ready().await;
+
+        tx.send(EventToSub::Set("a".into(), 80)).unwrap();
+        tx.send(EventToSub::Set("b".into(), 20)).unwrap();
+
+        tokio::time::timeout(TIMEOUT, async {
+            while harness.value("a", BRIGHTNESS_CODE) != Some(80)
+                || harness.value("b", BRIGHTNESS_CODE) != Some(20)
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the brightness wasn't written");
+    }
+
+    #[tokio::test]
+    async fn failed_write_is_reported() {
+        let mut harness =
+            Harness::start(vec![SimulatedMonitor::new("a", "A").with_failing_writes(1)]);
+
+        let (_, tx) = harness.ready().await;
+
+        tx.send(EventToSub::Set("a".into(), 80)).unwrap();
+
+        match harness.next().await {
+            AppMsg::MonitorStatusChanged(id, status) => {
+                assert_eq!(id, "a");
+                assert!(status.is_error());
+            }
+            msg => panic!("unexpected message {msg:?}"),
+        }
+        assert_eq!(harness.value("a", BRIGHTNESS_CODE), Some(50));
+    }
+
+    #[tokio::test]
+    async fn refresh_reports_values() {
+        let mut harness = Harness::start(vec![SimulatedMonitor::new("a", "A").with_value(
+            CONTRAST_CODE,
+            70,
+            100,
+        )]);
+
+        let (monitors, tx) = harness.ready().await;
+        assert_eq!(monitors["a"].contrast, Some(70));
+
+        // changed with the buttons of the monitor
+        {
+            let monitor = harness.backend.monitor("a").unwrap();
+            let mut monitor = monitor.lock().unwrap();
+            monitor.values.get_mut(&BRIGHTNESS_CODE).unwrap().value = 30;
+        }
+
+        tx.send(EventToSub::Refresh).unwrap();
+
+        let mut brightness = None;
+        let mut contrast = None;
+        while brightness.is_none() || contrast.is_none() {
+            match harness.next().await {
+                AppMsg::BrightnessWasUpdated(id, value) if id == "a" => brightness = Some(value),
+                AppMsg::ContrastWasUpdated(id, value) if id == "a" => contrast = Some(value),
+                msg => panic!("unexpected message {msg:?}"),
+            }
+        }
+
+        assert_eq!(brightness, Some(30));
+        assert_eq!(contrast, Some(70));
+    }
+
+    #[tokio::test]
+    async fn hotplug_updates_the_displays() {
+        let mut harness = Harness::start(vec![
+            SimulatedMonitor::new("a", "A"),
+            SimulatedMonitor::new("b", "B"),
+            // slow to probe, the rescan takes a while
+            SimulatedMonitor::new("c", "C")
+                .with_disconnected(true)
+                .with_latency(Duration::from_millis(200)),
+        ]);
+
+        let (monitors, tx) = harness.ready().await;
+        assert!(monitors.contains_key("a") && monitors.contains_key("b"));
+        assert!(!monitors.contains_key("c"));
+
+        harness.set_disconnected("b", true);
+        harness.set_disconnected("c", false);
+        harness.hotplug.send(()).unwrap();
+        harness.hotplug.send(()).unwrap();
+
+        // not held back by the rescan
+        tx.send(EventToSub::Set("a".into(), 80)).unwrap();
+
+        let mut removed = false;
+        let mut added = false;
+        while !removed || !added {
+            match harness.next().await {
+                AppMsg::MonitorRemoved(id) => {
+                    assert_eq!(id, "b");
+                    removed = true;
+                }
+                AppMsg::MonitorAdded(id, info) => {
+                    assert_eq!(id, "c");
+                    assert_eq!(info.status, MonitorStatus::Available);
+                    assert_eq!(harness.value("a", BRIGHTNESS_CODE), Some(80));
+                    added = true;
                 }
+                msg => panic!("unexpected message {msg:?}"),
+            }
+        }
+
+        // the remaining display keeps its worker
+        tx.send(EventToSub::Set("a".into(), 30)).unwrap();
+        tx.send(EventToSub::Set("c".into(), 60)).unwrap();
+
+        tokio::time::timeout(TIMEOUT, async {
+            while harness.value("a", BRIGHTNESS_CODE) != Some(30)
+                || harness.value("c", BRIGHTNESS_CODE) != Some(60)
+            {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the brightness wasn't written");
+    }
+
+    #[tokio::test]
+    async fn write_only_display_is_tested_and_probed_again() {
+        let mut harness = Harness::start(vec![
+            SimulatedMonitor::new("a", "A")
+                .with_value(BRIGHTNESS_CODE, 30, 100)
+                .with_write_only(true),
+        ]);
+
+        let (monitors, tx) = harness.ready().await;
+
+        // the test write landed
+        assert_eq!(monitors["a"].status, MonitorStatus::WriteOnly);
+        assert_eq!(monitors["a"].brightness, 50);
+        assert_eq!(harness.value("a", BRIGHTNESS_CODE), Some(50));
+
+        tx.send(EventToSub::Set("a".into(), 70)).unwrap();
+
+        // DDC/CI reads enabled again in the menu of the monitor
+        {
+            let monitor = harness.backend.monitor("a").unwrap();
+            monitor.lock().unwrap().write_only = false;
+        }
+
+        loop {
+            if let AppMsg::MonitorAdded(id, info) = harness.next().await {
+                assert_eq!(id, "a");
+                assert_eq!(info.status, MonitorStatus::Available);
+                assert_eq!(info.brightness, 70);
+                break;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn silent_display_is_not_write_only() {
+        let mut harness = Harness::start(vec![SimulatedMonitor::new("a", "A")]);
+
+        let (_, tx) = harness.ready().await;
+
+        // turned off, its capabilities are still cached
+        harness.set_disconnected("a", true);
+        tx.send(EventToSub::Refresh).unwrap();
+
+        match harness.next().await {
+            AppMsg::MonitorStatusChanged(id, status) => {
+                assert_eq!(id, "a");
+                assert_eq!(status, MonitorStatus::NoReply);
+            }
+            msg => panic!("unexpected message {msg:?}"),
+        }
+
+        // the background probe fails the same way
+        let probed = tokio::time::timeout(Duration::from_millis(2500), harness.next()).await;
+        if let Ok(msg) = probed {
+            panic!("unexpected message {msg:?}");
+        }
+
+        harness.set_disconnected("a", false);
+        tx.send(EventToSub::Retry("a".into())).unwrap();
+
+        match harness.next().await {
+            AppMsg::MonitorAdded(id, info) => {
+                assert_eq!(id, "a");
+                assert_eq!(info.status, MonitorStatus::Available);
             }
-        },
-    )
+            msg => panic!("unexpected message {msg:?}"),
+        }
+    }
 }
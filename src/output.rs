@@ -0,0 +1,54 @@
+use cosmic::{
+    cctk::{sctk::output::OutputInfo, wayland_client::Proxy, wayland_client::backend::ObjectId},
+    iced::{
+        Event, Subscription,
+        event::{self, PlatformSpecific, wayland},
+    },
+};
+
+use crate::app::AppMsg;
+
+/// Wayland output of the compositor, matched with a display by its connector name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WaylandOutput {
+    /// e.g. `DP-2`, the same name as the DRM connector
+    pub connector: String,
+    pub make: String,
+    pub model: String,
+    /// Top left corner in the compositor space
+    pub position: (i32, i32),
+}
+
+impl WaylandOutput {
+    fn new(info: OutputInfo) -> Option<Self> {
+        Some(Self {
+            connector: info.name?,
+            make: info.make,
+            model: info.model,
+            position: info.logical_position.unwrap_or(info.location),
+        })
+    }
+}
+
+/// Follow the Wayland outputs being added, moved or removed
+pub fn sub() -> Subscription<AppMsg> {
+    event::listen_with(|event, _, _| {
+        let Event::PlatformSpecific(PlatformSpecific::Wayland(wayland::Event::Output(
+            event,
+            output,
+        ))) = event
+        else {
+            return None;
+        };
+
+        let id: ObjectId = output.id();
+
+        match event {
+            wayland::OutputEvent::Created(Some(info)) | wayland::OutputEvent::InfoUpdate(info) => {
+                WaylandOutput::new(info).map(|output| AppMsg::OutputChanged(id, output))
+            }
+            wayland::OutputEvent::Created(None) => None,
+            wayland::OutputEvent::Removed => Some(AppMsg::OutputRemoved(id)),
+        }
+    })
+}
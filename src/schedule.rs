@@ -0,0 +1,293 @@
+use std::{f64::consts::PI, time::Duration};
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Timelike, Utc};
+use cosmic::iced::{Subscription, time};
+use serde::{Deserialize, Serialize};
+
+use crate::app::AppMsg;
+use crate::curve::Curve;
+
+/// Delay between two applications of the schedule
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+const HOURS_PER_DAY: f32 = 24.0;
+
+/// Brightness of the monitors at a time of the day
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ScheduleEntry {
+    /// Local time, e.g. `08:00`
+    pub time: String,
+    /// Position of the sliders, between 0 and 1
+    pub brightness: f32,
+}
+
+pub fn sub() -> Subscription<AppMsg> {
+    time::every(SCHEDULE_INTERVAL).map(|_| AppMsg::ScheduleTick)
+}
+
+/// Position of the sliders at this time of the day, interpolated between the entries.
+/// None if no entry is valid.
+pub fn scheduled_brightness(entries: &[ScheduleEntry], now: NaiveTime) -> Option<f32> {
+    let mut points: Vec<(f32, f32)> = entries
+        .iter()
+        .filter_map(
+            |entry| match NaiveTime::parse_from_str(&entry.time, "%H:%M") {
+                Ok(time) => Some((hours(time), entry.brightness.clamp(0.0, 1.0))),
+                Err(e) => {
+                    warn!("invalid time in schedule {:?}: {e}", entry.time);
+                    None
+                }
+            },
+        )
+        .collect();
+
+    points.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let (first, last) = (*points.first()?, *points.last()?);
+
+    // the schedule repeats every day, interpolate across midnight
+    points.insert(0, (last.0 - HOURS_PER_DAY, last.1));
+    points.push((first.0 + HOURS_PER_DAY, first.1));
+
+    Some(Curve::from(points).eval(hours(now)))
+}
+
+fn hours(time: NaiveTime) -> f32 {
+    time.num_seconds_from_midnight() as f32 / 3600.0
+}
+
+/// Brightness following the sun, at the configured place
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct SunSchedule {
+    /// In degrees, positive to the north
+    pub latitude: f64,
+    /// In degrees, positive to the east
+    pub longitude: f64,
+    /// Position of the sliders, between 0 and 1
+    pub day_brightness: f32,
+    pub night_brightness: f32,
+    /// Duration in minutes of the ramps, centered on the sunrise and the sunset
+    #[serde(default = "default_transition_minutes")]
+    pub transition_minutes: u32,
+}
+
+fn default_transition_minutes() -> u32 {
+    60
+}
+
+/// Sunrise and sunset of a day, in minutes from midnight UTC
+enum Daylight {
+    /// The sun doesn't rise, near the poles in winter
+    Night,
+    /// The sun doesn't set, near the poles in summer
+    Day,
+    Between {
+        sunrise: f64,
+        sunset: f64,
+    },
+}
+
+/// Approximation of the NOAA, accurate to a few minutes:
+/// <https://gml.noaa.gov/grad/solcalc/solareqns.PDF>
+fn daylight(date: NaiveDate, latitude: f64, longitude: f64) -> Daylight {
+    let g = 2.0 * PI / 365.0 * (date.ordinal0() as f64);
+
+    // minutes
+    let eqtime = 229.18
+        * (0.000075 + 0.001868 * g.cos()
+            - 0.032077 * g.sin()
+            - 0.014615 * (2.0 * g).cos()
+            - 0.040849 * (2.0 * g).sin());
+
+    // radians
+    let decl = 0.006918 - 0.399912 * g.cos() + 0.070257 * g.sin() - 0.006758 * (2.0 * g).cos()
+        + 0.000907 * (2.0 * g).sin()
+        - 0.002697 * (3.0 * g).cos()
+        + 0.00148 * (3.0 * g).sin();
+
+    let lat = latitude.to_radians();
+    // the sun is 0.833° below the horizon at sunrise, because of the refraction
+    let cos_ha = 90.833_f64.to_radians().cos() / (lat.cos() * decl.cos()) - lat.tan() * decl.tan();
+
+    if cos_ha > 1.0 {
+        return Daylight::Night;
+    }
+    if cos_ha < -1.0 {
+        return Daylight::Day;
+    }
+
+    let ha = cos_ha.acos().to_degrees();
+
+    Daylight::Between {
+        sunrise: 720.0 - 4.0 * (longitude + ha) - eqtime,
+        sunset: 720.0 - 4.0 * (longitude - ha) - eqtime,
+    }
+}
+
+/// Position of the sliders now, between the night and the day brightness
+pub fn sun_brightness(sun: &SunSchedule, now: DateTime<Utc>) -> f32 {
+    let today = now.date_naive();
+
+    let transition = sun.transition_minutes.max(1) as f64;
+    // 0 at the start of a ramp, 1 at its end
+    let ramp = |t: f64| ((t + transition / 2.0) / transition).clamp(0.0, 1.0);
+
+    // Far from Greenwich, the day of the place overlaps two days in UTC,
+    // e.g. the sun rises before midnight UTC in Asia
+    let daytime = |date: NaiveDate| {
+        let Daylight::Between { sunrise, sunset } = daylight(date, sun.latitude, sun.longitude)
+        else {
+            return None;
+        };
+        let midnight = date.and_time(NaiveTime::MIN).and_utc();
+        let minutes = (now - midnight).num_seconds() as f64 / 60.0;
+
+        Some(ramp(minutes - sunrise).min(ramp(sunset - minutes)))
+    };
+
+    let day = match daylight(today, sun.latitude, sun.longitude) {
+        Daylight::Night => 0.0,
+        Daylight::Day => 1.0,
+        Daylight::Between { .. } => [today.pred_opt(), Some(today), today.succ_opt()]
+            .into_iter()
+            .flatten()
+            .filter_map(daytime)
+            .fold(0.0, f64::max),
+    };
+
+    let (night, day_brightness) = (
+        sun.night_brightness.clamp(0.0, 1.0),
+        sun.day_brightness.clamp(0.0, 1.0),
+    );
+
+    night + (day_brightness - night) * day as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    /// Sunrise and sunset published for these days, in minutes from midnight UTC
+    fn assert_daylight(date: (i32, u32, u32), latitude: f64, longitude: f64, expected: (f64, f64)) {
+        let date = NaiveDate::from_ymd_opt(date.0, date.1, date.2).unwrap();
+
+        let Daylight::Between { sunrise, sunset } = daylight(date, latitude, longitude) else {
+            panic!("the sun should rise and set on {date}");
+        };
+
+        assert!((sunrise - expected.0).abs() < 5.0, "sunrise {sunrise}");
+        assert!((sunset - expected.1).abs() < 5.0, "sunset {sunset}");
+    }
+
+    fn sun(latitude: f64, longitude: f64) -> SunSchedule {
+        SunSchedule {
+            latitude,
+            longitude,
+            day_brightness: 0.8,
+            night_brightness: 0.2,
+            transition_minutes: 60,
+        }
+    }
+
+    fn assert_day(brightness: f32) {
+        assert!((brightness - 0.8).abs() < 1e-5, "{brightness}");
+    }
+
+    fn assert_night(brightness: f32) {
+        assert!((brightness - 0.2).abs() < 1e-5, "{brightness}");
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn mid_latitudes() {
+        // Paris, 05:47 and 21:58 CEST
+        assert_daylight((2024, 6, 21), 48.8566, 2.3522, (227.0, 1198.0));
+        // New York, 07:16 and 16:32 EST
+        assert_daylight((2024, 12, 21), 40.7128, -74.0060, (736.0, 1292.0));
+        // Sydney, 07:03 and 19:12 AEDT, the sun rises the day before in UTC
+        assert_daylight((2024, 3, 20), -33.8688, 151.2093, (-237.0, 492.0));
+    }
+
+    #[test]
+    fn polar_day_and_night() {
+        // Tromsø
+        assert!(matches!(
+            daylight(
+                NaiveDate::from_ymd_opt(2024, 6, 21).unwrap(),
+                69.6492,
+                18.9553
+            ),
+            Daylight::Day
+        ));
+        assert!(matches!(
+            daylight(
+                NaiveDate::from_ymd_opt(2024, 12, 21).unwrap(),
+                69.6492,
+                18.9553
+            ),
+            Daylight::Night
+        ));
+
+        let tromso = sun(69.6492, 18.9553);
+        assert_day(sun_brightness(&tromso, at(2024, 6, 21, 23, 0)));
+        assert_night(sun_brightness(&tromso, at(2024, 12, 21, 11, 0)));
+    }
+
+    #[test]
+    fn ramps_around_sunrise_and_sunset() {
+        let paris = sun(48.8566, 2.3522);
+
+        assert_day(sun_brightness(&paris, at(2024, 6, 21, 12, 0)));
+        assert_night(sun_brightness(&paris, at(2024, 6, 21, 1, 0)));
+
+        // halfway through the ramps, the estimate of the sunrise is off by a few minutes
+        let sunrise = sun_brightness(&paris, at(2024, 6, 21, 3, 47));
+        assert!((sunrise - 0.5).abs() < 0.1, "{sunrise}");
+        let sunset = sun_brightness(&paris, at(2024, 6, 21, 19, 58));
+        assert!((sunset - 0.5).abs() < 0.1, "{sunset}");
+    }
+
+    #[test]
+    fn day_across_midnight_utc() {
+        let sydney = sun(-33.8688, 151.2093);
+
+        // 08:00 and 10:00 AEDT, before and after midnight UTC
+        assert_day(sun_brightness(&sydney, at(2024, 3, 19, 21, 0)));
+        assert_day(sun_brightness(&sydney, at(2024, 3, 19, 23, 0)));
+        // 22:00 AEDT
+        assert_night(sun_brightness(&sydney, at(2024, 3, 20, 11, 0)));
+    }
+
+    #[test]
+    fn schedule_wraps_around_midnight() {
+        let entries = [
+            ScheduleEntry {
+                time: "22:00".into(),
+                brightness: 0.2,
+            },
+            ScheduleEntry {
+                time: "08:00".into(),
+                brightness: 0.8,
+            },
+            ScheduleEntry {
+                time: "invalid".into(),
+                brightness: 1.0,
+            },
+        ];
+
+        let brightness =
+            |h, m| scheduled_brightness(&entries, NaiveTime::from_hms_opt(h, m, 0).unwrap());
+
+        assert!((brightness(8, 0).unwrap() - 0.8).abs() < 1e-5);
+        assert!((brightness(22, 0).unwrap() - 0.2).abs() < 1e-5);
+        assert!((brightness(15, 0).unwrap() - 0.5).abs() < 1e-5);
+        // 10 hours from 22:00 to 08:00
+        assert!((brightness(3, 0).unwrap() - 0.5).abs() < 1e-5);
+        assert_eq!(scheduled_brightness(&[], NaiveTime::MIN), None);
+    }
+}
@@ -1,8 +1,11 @@
 use std::borrow::Cow;
 
-use crate::app::{AppMsg, AppState, MonitorState};
+use crate::app::{AppMsg, AppState, MonitorState, to_percent};
+use crate::config::Config;
+use crate::curve::Curve;
 use crate::fl;
 use crate::icon::{icon_high, icon_low, icon_medium, icon_off};
+use crate::monitor::{MonitorStatus, input_source_name};
 use cosmic::Element;
 use cosmic::applet::padded_control;
 use cosmic::iced::{Alignment, Length};
@@ -17,10 +20,10 @@ impl AppState {
             self.core
                 .applet
                 .icon_button_from_handle(
-                    self.monitors
-                        .values()
-                        .next()
-                        .map(|m| brightness_icon(m.slider_brightness))
+                    self.sorted_monitors()
+                        .into_iter()
+                        .find(|(_, m)| m.is_available())
+                        .map(|(_, m)| brightness_icon(m.slider_brightness))
                         .unwrap_or(icon_off()),
                 )
                 .on_press(AppMsg::TogglePopup),
@@ -37,7 +40,6 @@ impl AppState {
     }
 
     pub fn quick_settings_view(&self) -> Element<'_, AppMsg> {
-        #[allow(dead_code)]
         fn toggle_settings<'a>(
             info: impl Into<Cow<'a, str>> + 'a,
             value: bool,
@@ -50,32 +52,60 @@ impl AppState {
                 .into()
         }
 
-        column::with_capacity(1)
+        column::with_capacity(3)
             .width(Length::Fill)
             .spacing(20)
             .padding(10)
+            .push(toggle_settings(
+                fl!("ambient_light"),
+                self.config.ambient_light,
+                AppMsg::SetAmbientLight,
+            ))
+            .push(toggle_settings(
+                fl!("follow_internal"),
+                self.config.follows_internal(),
+                AppMsg::SetFollowInternal,
+            ))
             .push(button::text(fl!("refresh")).on_press(AppMsg::Refresh))
             .into()
     }
 
     pub fn popup_view(&self) -> Element<'_, AppMsg> {
-        column::with_capacity(3)
+        column::with_capacity(5)
             .padding(10)
             .push_maybe(self.monitors_view())
             .push_maybe(
                 (!self.monitors.is_empty()).then(|| padded_control(divider::horizontal::default())),
             )
+            .push_maybe(self.schedule_view())
             .push(self.dark_mode_view())
             .into()
     }
 
+    /// Shown when a schedule is configured, to resume it after a manual change
+    fn schedule_view(&self) -> Option<Element<'_, AppMsg>> {
+        self.config.has_schedule().then(|| {
+            padded_control(
+                row::with_capacity(3)
+                    .align_y(Alignment::Center)
+                    .push(text(fl!("schedule")))
+                    .push(horizontal())
+                    .push(
+                        toggler(!self.schedule_paused)
+                            .on_toggle(|enabled| AppMsg::SetSchedulePaused(!enabled)),
+                    ),
+            )
+            .into()
+        })
+    }
+
     fn monitors_view(&self) -> Option<Element<'_, AppMsg>> {
         (!self.monitors.is_empty()).then(|| {
             column::with_capacity(2)
                 .padding(8)
                 .extend(
-                    self.monitors
-                        .iter()
+                    self.sorted_monitors()
+                        .into_iter()
                         .map(|(id, monitor)| self.monitor_view(id, monitor)),
                 )
                 .into()
@@ -83,22 +113,30 @@ impl AppState {
     }
 
     fn monitor_view<'a>(&self, id: &'a str, monitor: &'a MonitorState) -> Element<'a, AppMsg> {
-        let gamma_map = self.config.get_gamma_map(id);
+        if !monitor.is_available() {
+            return unavailable_monitor_view(id, monitor);
+        }
+
+        let monitor_config = self.config.get_monitor_config(id);
+        let gamma_map = monitor_config.gamma_map;
+        let (floor, ceiling) = (monitor_config.floor, monitor_config.ceiling);
+        // the gamma and the range are replaced by a custom curve
+        let mapping_expanded = monitor.settings_expanded && monitor_config.curve.is_none();
 
         row::with_capacity(2)
             .padding(2.0)
             .push(
                 container(
                     mouse_area(
-                        column::with_capacity(2)
+                        column::with_capacity(7)
                             .spacing(8.0)
                             .padding(4.0)
                             .push(tooltip(
                                 icon::icon(brightness_icon(monitor.slider_brightness)).size(24),
-                                text(&monitor.name),
+                                text(monitor.label()),
                                 tooltip::Position::Right,
                             ))
-                            .push_maybe(monitor.settings_expanded.then(|| {
+                            .push_maybe(mapping_expanded.then(|| {
                                 tooltip(
                                     icon::from_name("emblem-system-symbolic")
                                         .size(24)
@@ -106,7 +144,57 @@ impl AppState {
                                     text(fl!("gamma_map")),
                                     tooltip::Position::Right,
                                 )
-                            })),
+                            }))
+                            .push_maybe(mapping_expanded.then(|| {
+                                tooltip(
+                                    icon::from_name("go-bottom-symbolic")
+                                        .size(24)
+                                        .symbolic(true),
+                                    text(fl!("range_floor")),
+                                    tooltip::Position::Right,
+                                )
+                            }))
+                            .push_maybe(mapping_expanded.then(|| {
+                                tooltip(
+                                    icon::from_name("go-top-symbolic").size(24).symbolic(true),
+                                    text(fl!("range_ceiling")),
+                                    tooltip::Position::Right,
+                                )
+                            }))
+                            .push_maybe(
+                                (monitor.settings_expanded && monitor.contrast.is_some()).then(
+                                    || {
+                                        tooltip(
+                                            icon::from_name("display-symbolic")
+                                                .size(24)
+                                                .symbolic(true),
+                                            text(fl!("contrast")),
+                                            tooltip::Position::Right,
+                                        )
+                                    },
+                                ),
+                            )
+                            .push_maybe(monitor.settings_expanded.then(|| {
+                                tooltip(
+                                    icon::from_name("insert-link-symbolic")
+                                        .size(24)
+                                        .symbolic(true),
+                                    text(fl!("link")),
+                                    tooltip::Position::Right,
+                                )
+                            }))
+                            .push_maybe(
+                                (monitor.settings_expanded && !monitor.input_sources().is_empty())
+                                    .then(|| {
+                                        tooltip(
+                                            icon::from_name("video-display-symbolic")
+                                                .size(24)
+                                                .symbolic(true),
+                                            text(fl!("input_source")),
+                                            tooltip::Position::Right,
+                                        )
+                                    }),
+                            ),
                     )
                     .on_press(AppMsg::ToggleMinMaxBrightness(id.to_string()))
                     .on_right_press(AppMsg::ToggleMonSettings(id.to_string()))
@@ -128,11 +216,11 @@ impl AppState {
                 }),
             )
             .push(
-                column::with_capacity(2)
+                column::with_capacity(7)
                     .spacing(8.0)
                     .padding(4.0)
                     .push(
-                        row::with_capacity(2)
+                        row::with_capacity(3)
                             .spacing(12)
                             .align_y(Alignment::Center)
                             .push(slider(
@@ -146,12 +234,24 @@ impl AppState {
                                 },
                             ))
                             .push(
-                                text(format!("{:.0}%", monitor.get_mapped_brightness(gamma_map)))
-                                    .size(16)
-                                    .width(Length::Fixed(35.0)),
-                            ),
+                                text(format!(
+                                    "{}%",
+                                    monitor.get_brightness_percent(&monitor_config)
+                                ))
+                                .size(16)
+                                .width(Length::Fixed(35.0)),
+                            )
+                            .push_maybe((!monitor.is_confirmed()).then(|| {
+                                tooltip(
+                                    icon::from_name("dialog-warning-symbolic")
+                                        .size(16)
+                                        .symbolic(true),
+                                    text(fl!("not_confirmed")),
+                                    tooltip::Position::Left,
+                                )
+                            })),
                     )
-                    .push_maybe(monitor.settings_expanded.then(|| {
+                    .push_maybe(mapping_expanded.then(|| {
                         row::with_capacity(2)
                             .spacing(12)
                             .align_y(Alignment::Center)
@@ -167,7 +267,51 @@ impl AppState {
                                     .size(16)
                                     .width(Length::Fixed(35.0)),
                             )
-                    })),
+                    }))
+                    .push_maybe(mapping_expanded.then(|| {
+                        range_view(floor, move |floor| {
+                            AppMsg::SetMonRange(id.to_string(), floor, ceiling)
+                        })
+                    }))
+                    .push_maybe(mapping_expanded.then(|| {
+                        range_view(ceiling, move |ceiling| {
+                            AppMsg::SetMonRange(id.to_string(), floor, ceiling)
+                        })
+                    }))
+                    .push_maybe(monitor.contrast.filter(|_| monitor.settings_expanded).map(
+                        |contrast| {
+                            row::with_capacity(2)
+                                .spacing(12)
+                                .align_y(Alignment::Center)
+                                .push(slider(
+                                    0..=monitor.max_contrast,
+                                    contrast,
+                                    move |contrast| AppMsg::SetContrast(id.to_string(), contrast),
+                                ))
+                                .push(
+                                    text(format!(
+                                        "{}%",
+                                        to_percent(contrast, monitor.max_contrast)
+                                    ))
+                                    .size(16)
+                                    .width(Length::Fixed(35.0)),
+                                )
+                        },
+                    ))
+                    .push_maybe(
+                        monitor
+                            .settings_expanded
+                            .then(|| link_view(id, &self.config)),
+                    )
+                    .push_maybe(
+                        (monitor.settings_expanded && !monitor.input_sources().is_empty())
+                            .then(|| input_source_view(id, monitor)),
+                    )
+                    .push_maybe(
+                        monitor
+                            .settings_expanded
+                            .then(|| curve_view(id, monitor_config.curve)),
+                    ),
             )
             .into()
     }
@@ -260,6 +404,139 @@ impl AppState {
     }
 }
 
+fn unavailable_monitor_view<'a>(id: &'a str, monitor: &'a MonitorState) -> Element<'a, AppMsg> {
+    let label = monitor.label();
+    let name = label.as_str();
+
+    let explanation = match monitor.status {
+        MonitorStatus::Available | MonitorStatus::WriteOnly => String::new(),
+        MonitorStatus::PermissionDenied => fl!("status_permission_denied", name = name),
+        MonitorStatus::NoReply => fl!("status_no_reply", name = name),
+        MonitorStatus::ChecksumError => fl!("status_checksum_error", name = name),
+        MonitorStatus::Unsupported => fl!("unsupported", name = name),
+        MonitorStatus::Error => fl!("status_error", name = name),
+    };
+
+    row::with_capacity(2)
+        .padding(2.0)
+        .spacing(12)
+        .align_y(Alignment::Center)
+        .push(
+            container(tooltip(
+                icon::icon(icon_off()).size(24),
+                text(label),
+                tooltip::Position::Right,
+            ))
+            .padding(4.0),
+        )
+        .push(
+            column::with_capacity(2)
+                .spacing(8.0)
+                .push(text(explanation))
+                .push_maybe(monitor.status.is_error().then(|| {
+                    button::text(fl!("retry")).on_press(AppMsg::RetryMonitor(id.to_string()))
+                })),
+        )
+        .into()
+}
+
+/// Link group of a monitor, to pick among the existing ones or a new one
+fn link_view<'a>(id: &'a str, config: &Config) -> Element<'a, AppMsg> {
+    let current = config.link_group_index(id);
+    // a monitor alone in its group can't join a new one
+    let alone = config
+        .link_group(id)
+        .is_some_and(|group| group.members.len() == 1);
+    let groups = config.link_groups.len();
+
+    let choices = std::iter::once((None, fl!("link_none")))
+        .chain((0..groups).map(|index| (Some(index), fl!("link_group", index = index + 1))))
+        .chain((!alone).then(|| (Some(groups), fl!("link_new"))));
+
+    column::with_capacity(groups + 3)
+        .spacing(4)
+        .push(text(fl!("link")))
+        .extend(choices.map(|(index, label)| {
+            button::text(label)
+                .width(Length::Fill)
+                .class(if current == index {
+                    cosmic::theme::Button::Suggested
+                } else {
+                    cosmic::theme::Button::Text
+                })
+                .on_press(AppMsg::SetMonLinkGroup(id.to_string(), index))
+                .into()
+        }))
+        .into()
+}
+
+/// Toggle of the custom curve, and a slider per control point when it's enabled
+fn curve_view(id: &str, curve: Option<Curve>) -> Element<'_, AppMsg> {
+    let points = curve.as_ref().map(Curve::points).unwrap_or_default();
+
+    column::with_capacity(points.len() + 1)
+        .spacing(4)
+        .push(
+            row::with_capacity(3)
+                .spacing(12)
+                .align_y(Alignment::Center)
+                .push(text(fl!("custom_curve")))
+                .push(horizontal())
+                .push(
+                    toggler(curve.is_some())
+                        .on_toggle(move |enabled| AppMsg::SetMonCurve(id.to_string(), enabled)),
+                ),
+        )
+        .extend(points.iter().enumerate().map(|(index, &(x, y))| {
+            row::with_capacity(2)
+                .spacing(12)
+                .align_y(Alignment::Center)
+                .push(
+                    text(format!("{}%", (x * 100.0).round()))
+                        .size(16)
+                        .width(Length::Fixed(35.0)),
+                )
+                .push(range_view(y, move |y| {
+                    AppMsg::SetMonCurvePoint(id.to_string(), index, y)
+                }))
+                .into()
+        }))
+        .into()
+}
+
+/// Slider of a part of the hardware range, between 0 and 1
+fn range_view<'a>(value: f32, f: impl Fn(f32) -> AppMsg + 'a) -> Element<'a, AppMsg> {
+    row::with_capacity(2)
+        .spacing(12)
+        .align_y(Alignment::Center)
+        .push(slider(0..=100, (value * 100.0).round() as u16, move |v| {
+            f(v as f32 / 100.0)
+        }))
+        .push(
+            text(format!("{}%", (value * 100.0).round()))
+                .size(16)
+                .width(Length::Fixed(35.0)),
+        )
+        .into()
+}
+
+fn input_source_view<'a>(id: &'a str, monitor: &'a MonitorState) -> Element<'a, AppMsg> {
+    column::with_capacity(monitor.input_sources().len())
+        .spacing(4)
+        .extend(monitor.input_sources().iter().map(|&input_source| {
+            button::text(input_source_name(input_source))
+                .width(Length::Fill)
+                .class(if monitor.input_source == Some(input_source) {
+                    cosmic::theme::Button::Suggested
+                } else {
+                    cosmic::theme::Button::Text
+                })
+                .on_press(AppMsg::SetInputSource(id.to_string(), input_source))
+                .into()
+        }))
+        .into()
+}
+
 fn brightness_icon(brightness: f32) -> icon::Handle {
     if brightness > 0.66 {
         icon_high()
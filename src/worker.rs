@@ -0,0 +1,444 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use cosmic::iced::futures::{SinkExt, channel::mpsc::Sender};
+use tokio::{
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel},
+        watch,
+    },
+    time::Instant,
+};
+
+use crate::app::AppMsg;
+use crate::backend::DisplayHandle;
+use crate::capabilities::{Capabilities, supports};
+use crate::monitor::{
+    BRIGHTNESS_CODE, CONTRAST_CODE, DisplayId, INPUT_SOURCE_CODE, MonitorInfo, MonitorStatus,
+    fetch_capabilities, input_source_value, probe,
+};
+
+/// Time to let the monitor breathe after a write
+const WRITE_DELAY: Duration = Duration::from_millis(50);
+
+/// First delay before probing a failing display in the background, doubled after each failure
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// Number of reads after a brightness write, the value is written again between them
+const VERIFY_ATTEMPTS: usize = 3;
+
+/// Maximum number of writes of a brightness transition, DDC/CI is slow
+const TRANSITION_STEPS: usize = 10;
+
+/// Settings of the workers, taken from the config of the app
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkerSettings {
+    /// Read the brightness back after writing it, for monitors that silently ignore writes
+    pub verify_writes: bool,
+    /// Duration of the fade to a new brightness, instant if None
+    pub transition: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Command {
+    /// With `only_changes`, only values that differ from the last known ones are sent
+    Read {
+        only_changes: bool,
+    },
+    Set(u8, u16),
+    /// Read the whole state of the display again, after a failure
+    Probe,
+}
+
+/// Process the commands of one display, independently of the other displays.
+///
+/// A slow or hung i2c bus only delays its own monitor.
+pub struct Worker {
+    id: DisplayId,
+    handle: Arc<Mutex<Box<dyn DisplayHandle>>>,
+    capabilities: Option<Capabilities>,
+    /// Last known values, to only report changes when polling
+    brightness: Option<u16>,
+    contrast: Option<u16>,
+    input_source: Option<u16>,
+    status: MonitorStatus,
+    /// Delay before the next background probe, while the display fails
+    retry_delay: Duration,
+    /// No probe before this, unless asked by the user
+    next_probe: Instant,
+    /// Target of a transition interrupted by another command
+    pending_brightness: Option<u16>,
+    settings: watch::Receiver<WorkerSettings>,
+    output: Sender<AppMsg>,
+}
+
+impl Worker {
+    /// The worker stops when the returned sender is dropped
+    pub fn spawn(
+        id: DisplayId,
+        handle: Box<dyn DisplayHandle>,
+        info: &MonitorInfo,
+        settings: watch::Receiver<WorkerSettings>,
+        output: Sender<AppMsg>,
+    ) -> UnboundedSender<Command> {
+        let (tx, rx) = unbounded_channel();
+
+        let mut worker = Worker {
+            id,
+            handle: Arc::new(Mutex::new(handle)),
+            capabilities: info.capabilities.clone(),
+            brightness: Some(info.brightness),
+            contrast: info.contrast,
+            input_source: info.input_source,
+            status: info.status,
+            retry_delay: RETRY_DELAY,
+            next_probe: Instant::now(),
+            pending_brightness: None,
+            settings,
+            output,
+        };
+
+        // the enumeration just probed it
+        if worker.status.needs_probe() {
+            worker.schedule_probe();
+        }
+
+        tokio::spawn(worker.run(rx));
+
+        tx
+    }
+
+    async fn run(mut self, mut rx: UnboundedReceiver<Command>) {
+        while let Some(command) = self.next_command(&mut rx).await {
+            // Commands pile up while the monitor is busy, e.g. when dragging a slider.
+            // Only the last value of each feature is worth writing.
+            let mut sets = BTreeMap::new();
+            let mut read = None;
+            let mut reprobe = false;
+
+            let mut command = Some(command);
+            while let Some(c) = command.take().or_else(|| rx.try_recv().ok()) {
+                match c {
+                    Command::Set(code, value) => {
+                        sets.insert(code, value);
+                    }
+                    Command::Read { only_changes } => {
+                        read = Some(read.unwrap_or(true) && only_changes);
+                    }
+                    Command::Probe => reprobe = true,
+                }
+            }
+
+            if let Some(value) = self.pending_brightness.take() {
+                sets.entry(BRIGHTNESS_CODE).or_insert(value);
+            }
+
+            // a failing display has nothing valid to read, try to probe it instead,
+            // without hammering the bus when the app polls
+            let probe_due = self.status.needs_probe() && Instant::now() >= self.next_probe;
+            if reprobe || (read.is_some() && probe_due) {
+                self.probe().await;
+                // the probe sent the whole state already
+                read = None;
+
+                if self.status.is_error() {
+                    continue;
+                }
+            }
+            if self.status.is_error() {
+                read = None;
+            }
+
+            // verifying a value that is about to be replaced would move the slider back
+            let verify = self.settings.borrow().verify_writes && rx.is_empty();
+
+            for (code, value) in sets {
+                if code == BRIGHTNESS_CODE && !self.transition(value, &rx).await {
+                    // a new command arrived, the next batch starts from the current value
+                    self.pending_brightness = Some(value);
+                    continue;
+                }
+                self.set(code, value, verify).await;
+            }
+
+            // the monitor doesn't answer, the last value written is all we know
+            if let Some(only_changes) = read.filter(|_| self.status != MonitorStatus::WriteOnly) {
+                self.read(only_changes).await;
+            }
+        }
+
+        debug!("worker of {} stopped", self.id);
+    }
+
+    /// Wait for a command. While the display fails, it is probed again after a growing delay,
+    /// e.g. the "Expected DDC/CI length bit" error often clears on its own after the login.
+    /// A write-only display is probed the same way, in case it answers reads again.
+    async fn next_command(&mut self, rx: &mut UnboundedReceiver<Command>) -> Option<Command> {
+        if !self.status.needs_probe() {
+            return rx.recv().await;
+        }
+
+        match tokio::time::timeout_at(self.next_probe, rx.recv()).await {
+            Ok(command) => command,
+            Err(_) => {
+                debug!("probe {} again", self.id);
+                Some(Command::Probe)
+            }
+        }
+    }
+
+    fn schedule_probe(&mut self) {
+        self.next_probe = Instant::now() + self.retry_delay;
+        self.retry_delay = (self.retry_delay * 2).min(MAX_RETRY_DELAY);
+    }
+
+    async fn set(&mut self, code: u8, value: u16, verify: bool) {
+        if !supports(self.capabilities.as_ref(), code) {
+            warn!("{} doesn't support feature {code:#04x}", self.id);
+            return;
+        }
+
+        let verify = verify && self.status != MonitorStatus::WriteOnly;
+
+        if self.write(code, value).await && verify && code == BRIGHTNESS_CODE {
+            self.verify(value).await;
+        }
+    }
+
+    /// Write the intermediate values of a fade to a new brightness, the last one is left to `set`.
+    ///
+    /// Return false if a command arrived during the transition, to handle it first.
+    async fn transition(&mut self, target: u16, rx: &UnboundedReceiver<Command>) -> bool {
+        let transition = self.settings.borrow().transition;
+        let Some(duration) = transition else {
+            return true;
+        };
+        // the current brightness of other monitors is unknown
+        let Some(from) = self
+            .brightness
+            .filter(|_| self.status == MonitorStatus::Available)
+        else {
+            return true;
+        };
+
+        let diff = target as i32 - from as i32;
+        let steps = (diff.unsigned_abs() as usize).min(TRANSITION_STEPS);
+        let delay = (duration / steps.max(1) as u32).saturating_sub(WRITE_DELAY);
+
+        for step in 1..steps {
+            let value = from as i32 + diff * step as i32 / steps as i32;
+
+            if !self.write(BRIGHTNESS_CODE, value as u16).await {
+                return true;
+            }
+
+            tokio::time::sleep(delay).await;
+
+            if !rx.is_empty() {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Return false if the write failed
+    async fn write(&mut self, code: u8, value: u16) -> bool {
+        let handle = Arc::clone(&self.handle);
+
+        let j = tokio::task::spawn_blocking(move || {
+            handle.lock().unwrap().set_vcp_feature(code, value)
+        });
+
+        let res = match j.await.unwrap() {
+            Ok(()) => {
+                self.remember(code, value);
+                true
+            }
+            Err(err) => {
+                error!("{:?}", err);
+                self.set_status(MonitorStatus::from_error(&err)).await;
+                false
+            }
+        };
+
+        tokio::time::sleep(WRITE_DELAY).await;
+
+        res
+    }
+
+    /// Read the brightness back until the monitor reports the written value,
+    /// and show the value it actually uses
+    async fn verify(&mut self, value: u16) {
+        for attempt in 1..=VERIFY_ATTEMPTS {
+            let Some(reported) = self.read_back(BRIGHTNESS_CODE).await else {
+                return;
+            };
+
+            if reported == value || attempt == VERIFY_ATTEMPTS {
+                if reported != value {
+                    warn!("{} kept brightness {reported} instead of {value}", self.id);
+                }
+                self.brightness = Some(reported);
+                self.send(AppMsg::BrightnessWasUpdated(self.id.clone(), reported))
+                    .await;
+                return;
+            }
+
+            debug!(
+                "{} reported {reported} instead of {value}, write again",
+                self.id
+            );
+            if !self.write(BRIGHTNESS_CODE, value).await {
+                return;
+            }
+        }
+    }
+
+    async fn read_back(&mut self, code: u8) -> Option<u16> {
+        let handle = Arc::clone(&self.handle);
+
+        let j = tokio::task::spawn_blocking(move || handle.lock().unwrap().get_vcp_feature(code));
+
+        match j.await.unwrap() {
+            Ok(value) => Some(value.value),
+            Err(err) => {
+                debug!("can't read back {code:#04x} of {}: {err:?}", self.id);
+                None
+            }
+        }
+    }
+
+    async fn read(&mut self, only_changes: bool) {
+        let id = self.id.clone();
+        let handle = Arc::clone(&self.handle);
+        let caps = self.capabilities.clone();
+
+        let j = tokio::task::spawn_blocking(move || {
+            let caps = caps.as_ref();
+            let mut handle = handle.lock().unwrap();
+            (
+                supports(caps, BRIGHTNESS_CODE).then(|| handle.get_vcp_feature(BRIGHTNESS_CODE)),
+                supports(caps, CONTRAST_CODE).then(|| handle.get_vcp_feature(CONTRAST_CODE)),
+                caps.is_some_and(|caps| caps.supports(INPUT_SOURCE_CODE))
+                    .then(|| handle.get_vcp_feature(INPUT_SOURCE_CODE)),
+            )
+        });
+
+        let (brightness, contrast, input_source) = j.await.unwrap();
+
+        match brightness {
+            None => {}
+            Some(Ok(value)) => {
+                self.set_status(MonitorStatus::Available).await;
+                if !only_changes || self.brightness != Some(value.value) {
+                    self.brightness = Some(value.value);
+                    self.send(AppMsg::BrightnessWasUpdated(id.clone(), value.value))
+                        .await;
+                }
+            }
+            Some(Err(err)) => {
+                error!("{:?}", err);
+                self.set_status(MonitorStatus::from_error(&err)).await;
+            }
+        }
+
+        match contrast {
+            None => {}
+            Some(Ok(value)) => {
+                if !only_changes || self.contrast != Some(value.value) {
+                    self.contrast = Some(value.value);
+                    self.send(AppMsg::ContrastWasUpdated(id.clone(), value.value))
+                        .await;
+                }
+            }
+            Some(Err(err)) => debug!("can't get contrast of {id}: {err:?}"),
+        }
+
+        match input_source {
+            None => {}
+            Some(Ok(value)) => {
+                let value = input_source_value(value.value);
+                if !only_changes || self.input_source != Some(value) {
+                    self.input_source = Some(value);
+                    self.send(AppMsg::InputSourceWasUpdated(id.clone(), value))
+                        .await;
+                }
+            }
+            Some(Err(err)) => debug!("can't get input source of {id}: {err:?}"),
+        }
+    }
+
+    async fn probe(&mut self) {
+        let handle = Arc::clone(&self.handle);
+        let caps = self.capabilities.clone();
+        let brightness = self.brightness;
+
+        let j = tokio::task::spawn_blocking(move || {
+            let mut handle = handle.lock().unwrap();
+            let caps = caps.or_else(|| fetch_capabilities(&mut handle));
+            probe(&mut handle, caps, brightness)
+        });
+
+        match j.await.unwrap() {
+            Ok(info) => {
+                let still_write_only = self.status == MonitorStatus::WriteOnly
+                    && info.status == MonitorStatus::WriteOnly;
+
+                self.capabilities = info.capabilities.clone();
+                self.brightness = Some(info.brightness);
+                self.contrast = info.contrast;
+                self.input_source = info.input_source;
+                self.status = info.status;
+
+                if info.status.needs_probe() {
+                    self.schedule_probe();
+                }
+                if !still_write_only {
+                    info!("{} is available again", self.id);
+                    self.send(AppMsg::MonitorAdded(self.id.clone(), info)).await;
+                }
+            }
+            Err(err) => {
+                error!("can't probe {}: {err}", self.id);
+                let failing = self.status.needs_probe();
+                self.set_status(MonitorStatus::from_error(&err)).await;
+                // otherwise the new failure scheduled the first probe
+                if failing {
+                    self.schedule_probe();
+                }
+            }
+        }
+    }
+
+    async fn set_status(&mut self, status: MonitorStatus) {
+        if self.status != status {
+            if status.needs_probe() && !self.status.needs_probe() {
+                self.retry_delay = RETRY_DELAY;
+                self.schedule_probe();
+            }
+            self.status = status;
+            self.send(AppMsg::MonitorStatusChanged(self.id.clone(), status))
+                .await;
+        }
+    }
+
+    fn remember(&mut self, code: u8, value: u16) {
+        match code {
+            BRIGHTNESS_CODE => self.brightness = Some(value),
+            CONTRAST_CODE => self.contrast = Some(value),
+            INPUT_SOURCE_CODE => self.input_source = Some(value),
+            _ => {}
+        }
+    }
+
+    async fn send(&mut self, msg: AppMsg) {
+        if let Err(e) = self.output.send(msg).await {
+            debug!("can't send update of {}: {e}", self.id);
+        }
+    }
+}